@@ -0,0 +1,74 @@
+/// Rate of the samples handed to the frontend
+pub const SAMPLE_RATE: u32 = 44_100;
+
+pub const NTSC_CPU_CLOCK: f64 = 1_789_773.0;
+pub const PAL_CPU_CLOCK: f64 = 1_662_607.0;
+
+/// Turns the per-CPU-cycle output level of the sound sources into samples
+/// at `SAMPLE_RATE`, averaging all the cycles that fall into one sample
+pub struct Mixer {
+    cycles_per_sample: f64,
+    cycles_into_sample: f64,
+    level_sum: f32,
+    level_count: u32,
+    samples: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(cpu_clock: f64) -> Self {
+        Mixer {
+            cycles_per_sample: cpu_clock / SAMPLE_RATE as f64,
+            cycles_into_sample: 0.0,
+            level_sum: 0.0,
+            level_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Feeds `cycles` CPU cycles during which the output stayed at `level`
+    pub fn tick(&mut self, cycles: u8, level: f32) {
+        for _ in 0..cycles {
+            self.level_sum += level;
+            self.level_count += 1;
+            self.cycles_into_sample += 1.0;
+
+            if self.cycles_into_sample >= self.cycles_per_sample {
+                self.cycles_into_sample -= self.cycles_per_sample;
+                self.samples.push(self.level_sum / self.level_count as f32);
+                self.level_sum = 0.0;
+                self.level_count = 0;
+            }
+        }
+    }
+
+    /// Hands over every sample produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sample_rate() {
+        let mut mixer = Mixer::new(NTSC_CPU_CLOCK);
+        for _ in 0..(NTSC_CPU_CLOCK as usize / 7) {
+            mixer.tick(7, 0.5);
+        }
+
+        let samples = mixer.take_samples();
+        assert!((samples.len() as i64 - SAMPLE_RATE as i64).abs() <= 1);
+        assert!(samples.iter().all(|&sample| sample == 0.5));
+        assert!(mixer.take_samples().is_empty());
+    }
+
+    #[test]
+    fn test_averages_levels() {
+        let mut mixer = Mixer::new(SAMPLE_RATE as f64 * 4.0);
+        mixer.tick(2, 1.0);
+        mixer.tick(2, 0.0);
+        assert_eq!(mixer.take_samples(), vec![0.5]);
+    }
+}
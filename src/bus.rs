@@ -1,66 +1,277 @@
+use crate::audio;
+use crate::audio::Mixer;
 use crate::cpu::Mem;
+use crate::mapper;
+use crate::mapper::Mapper;
+use crate::ppu::PPU;
 use crate::rom::Rom;
+use crate::rom::Timing;
 
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+/// APU and controller registers, which aren't emulated yet
+const IO_REGISTERS: u16 = 0x4000;
+const IO_REGISTERS_END: u16 = 0x401F;
+const OAM_DMA: u16 = 0x4014;
+const CARTRIDGE: u16 = 0x4020;
+const CARTRIDGE_END: u16 = 0xFFFF;
+
+/// PPU dots per CPU cycle, in fifths: 3 on NTSC, 3.2 on PAL
+const NTSC_DOTS_PER_CYCLE: u16 = 15;
+const PAL_DOTS_PER_CYCLE: u16 = 16;
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
-    rom: Rom,
+    mapper: Box<dyn Mapper>,
+    ppu: PPU,
+    mixer: Mixer,
+    battery: bool,
+    /// Set by a $4014 write until the CPU has accounted for the stall
+    oam_dma_stall: bool,
+    /// Last value driven on the data bus, which reads of unmapped registers
+    /// see again
+    open_bus: u8,
+    dots_per_cycle: u16,
+    /// Fifths of a dot carried over between ticks on PAL
+    dot_remainder: u16,
 }
 
 impl Bus {
-    pub fn new() -> Self {
+    pub fn new(rom: Rom) -> Self {
+        let (cpu_clock, dots_per_cycle) = match rom.timing {
+            Timing::Pal => (audio::PAL_CPU_CLOCK, PAL_DOTS_PER_CYCLE),
+            Timing::Ntsc | Timing::MultiRegion => (audio::NTSC_CPU_CLOCK, NTSC_DOTS_PER_CYCLE),
+            // the Dendy's own CPU clock and 312 line frame aren't emulated,
+            // its ROMs fall back to NTSC timing here and in the PPU
+            Timing::Dendy => (audio::NTSC_CPU_CLOCK, NTSC_DOTS_PER_CYCLE),
+        };
+        let timing = rom.timing;
+
+        let battery = rom.battery;
+
         Self {
             cpu_vram: [0; 2048],
-            rom: Rom::new(&vec![]).unwrap(),
+            mapper: mapper::new(rom),
+            ppu: PPU::with_timing(timing),
+            mixer: Mixer::new(cpu_clock),
+            battery,
+            oam_dma_stall: false,
+            open_bus: 0,
+            dots_per_cycle,
+            dot_remainder: 0,
+        }
+    }
+
+    /// Advances the cartridge and the PPU by the CPU cycles the last
+    /// instruction took. Completed frames are reported through
+    /// `take_frame_complete`, vblank NMIs through `poll_nmi`
+    pub fn tick(&mut self, cycles: u8) {
+        self.mapper.cpu_tick(cycles);
+        self.mixer.tick(cycles, self.mapper.audio_output());
+
+        let fifths = self.dot_remainder + cycles as u16 * self.dots_per_cycle;
+        self.dot_remainder = fifths % 5;
+        for _ in 0..fifths / 5 {
+            self.ppu.tick(self.mapper.as_mut());
+        }
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    /// Whether the PPU raised an NMI since the last call
+    pub fn poll_nmi(&mut self) -> bool {
+        self.ppu.poll_nmi()
+    }
+
+    /// Whether the PPU finished a frame since the last call, ready in
+    /// `ppu().frame` for presenting
+    pub fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+
+    /// Audio samples produced since the last call, at `audio::SAMPLE_RATE`
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.mixer.take_samples()
+    }
+
+    /// Whether an OAM DMA happened since the last call. The CPU owes the
+    /// transfer's stall cycles once the writing instruction completes
+    pub fn take_oam_dma_stall(&mut self) -> bool {
+        std::mem::take(&mut self.oam_dma_stall)
+    }
+
+    /// Copies page $XX00 into OAM, starting at OAMADDR like writes to $2004
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for offset in 0..=0xff {
+            let data = self.mem_read(base | offset);
+            self.ppu.write_oam(data);
         }
+        self.oam_dma_stall = true;
     }
 
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        if self.rom.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-            addr = addr % 0x4000;
+    /// Battery backed cartridge RAM, `None` when the cartridge has no battery
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        if self.battery {
+            self.mapper.save_ram()
+        } else {
+            None
         }
+    }
 
-        self.rom.prg_rom[addr as usize]
+    /// Restores battery backed RAM from a previous session. A save of the
+    /// wrong size is copied as far as it fits
+    pub fn load_battery_ram(&mut self, data: &[u8]) {
+        if !self.battery {
+            return;
+        }
+        if let Some(ram) = self.mapper.save_ram_mut() {
+            let len = ram.len().min(data.len());
+            ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    /// Level of the cartridge IRQ line
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
     }
 }
 
 impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        let data = match addr {
+            PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.read_register(addr, self.mapper.as_mut())
+            },
+            CARTRIDGE ..= CARTRIDGE_END => self.mapper.cpu_read(addr),
+            _ => self.mem_peek(addr),
+        };
+        self.open_bus = data;
+        data
+    }
+    fn mem_peek(&self, addr: u16) -> u8 {
         match addr {
             RAM ..= RAM_MIRRORS_END => {
                 let mirror_donw_addr = addr & 0b0000_0111_1111_1111;
                 self.cpu_vram[mirror_donw_addr as usize]
             },
             PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
-                println!("PPU registers not implemented yet");
-                0
+                self.ppu.peek_register(addr)
             },
-            0x8000..=0xFFFF => {
-                self.read_prg_rom(addr)
-            }
-            _ => {
-                println!("Address not implemented yet");
-                0
+            IO_REGISTERS ..= IO_REGISTERS_END => self.open_bus,
+            CARTRIDGE ..= CARTRIDGE_END => {
+                self.mapper.cpu_peek(addr)
             },
         }
     }
     fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
         match addr {
             RAM ..= RAM_MIRRORS_END => {
                 let mirror_donw_addr = addr & 0b0000_0111_1111_1111;
                 self.cpu_vram[mirror_donw_addr as usize] = data;
             },
             PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
-                println!("PPU registers not implemented yet");
+                self.mapper.ppu_register_write(addr & 0x2007, data);
+                self.ppu.write_register(addr, data, self.mapper.as_mut());
             },
-            _ => {
-                println!("Address not implemented yet");
+            OAM_DMA => self.oam_dma(data),
+            IO_REGISTERS ..= IO_REGISTERS_END => {},
+            CARTRIDGE ..= CARTRIDGE_END => {
+                self.mapper.cpu_write(addr, data);
             },
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::{create_rom, TestRom};
+
+    #[test]
+    fn test_trainer_loaded_into_prg_ram() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0b100, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00,
+            ],
+            trainer: Some((0..512).map(|i| i as u8).collect()),
+            prg_rom: vec![0; 0x4000],
+            chr_rom: vec![0; 0x2000],
+        });
+        let mut bus = Bus::new(Rom::new(&raw).unwrap());
+
+        assert_eq!(bus.mem_read(0x6fff), 0x00);
+        assert_eq!(bus.mem_read(0x7000), 0x00);
+        assert_eq!(bus.mem_read(0x7001), 0x01);
+        assert_eq!(bus.mem_read(0x71ff), 0xff);
+        assert_eq!(bus.mem_read(0x7200), 0x00);
+    }
+
+    #[test]
+    fn test_io_registers_read_open_bus() {
+        let mut bus = Bus::new(crate::rom::test::test_rom());
+        bus.mem_write(0x0010, 0x42);
+        bus.mem_write(0x4000, 0x99);
+        bus.mem_read(0x0010);
+        assert_eq!(bus.mem_peek(0x4015), 0x42);
+        assert_eq!(bus.mem_read(0x401f), 0x42);
+    }
+
+    #[test]
+    fn test_oam_dma() {
+        let mut bus = Bus::new(crate::rom::test::test_rom());
+        for i in 0..=0xff {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        bus.mem_write(0x2003, 0x10);
+        bus.mem_write(0x4014, 0x02);
+
+        assert!(bus.take_oam_dma_stall());
+        assert!(!bus.take_oam_dma_stall());
+        assert_eq!(bus.ppu.oam_data[0x10], 0x00);
+        assert_eq!(bus.ppu.oam_data[0xff], 0xef);
+        assert_eq!(bus.ppu.oam_data[0x00], 0xf0);
+        assert_eq!(bus.ppu.oam_addr, 0x10);
+    }
+
+    #[test]
+    fn test_tick_advances_ppu() {
+        let mut bus = Bus::new(crate::rom::test::test_rom());
+        bus.tick(2);
+        assert_eq!(bus.ppu().dot, 6);
+        bus.tick(114);
+        assert_eq!((bus.ppu().scanline, bus.ppu().dot), (1, 7));
+    }
+
+    #[test]
+    fn test_tick_pal() {
+        let mut rom = crate::rom::test::test_rom();
+        rom.timing = Timing::Pal;
+        let mut bus = Bus::new(rom);
+        for _ in 0..5 {
+            bus.tick(1);
+        }
+        assert_eq!(bus.ppu().dot, 16);
+    }
+
+    #[test]
+    fn test_frame_complete() {
+        let mut bus = Bus::new(crate::rom::test::test_rom());
+        bus.mem_write(0x2000, 0x80);
+        // vblank starts on dot 1 of line 241
+        let cycles = (241 * 341 + 1) / 3;
+        for _ in 0..cycles {
+            bus.tick(1);
+        }
+        assert!(!bus.take_frame_complete());
+        bus.tick(1);
+        assert!(bus.take_frame_complete());
+        assert!(bus.poll_nmi());
+    }
+}
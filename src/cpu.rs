@@ -17,6 +17,59 @@ bitflags! {
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
 
+fn page_cross(addr1: u16, addr2: u16) -> bool {
+    addr1 & 0xFF00 != addr2 & 0xFF00
+}
+
+mod interrupt {
+    #[derive(PartialEq, Eq)]
+    pub enum InterruptType {
+        Nmi,
+        Irq,
+        Brk,
+    }
+
+    #[derive(PartialEq, Eq)]
+    pub(super) struct Interrupt {
+        pub(super) itype: InterruptType,
+        pub(super) vector_addr: u16,
+        pub(super) b_flag_mask: u8,
+        pub(super) cpu_cycles: u8,
+    }
+
+    pub(super) const NMI: Interrupt = Interrupt {
+        itype: InterruptType::Nmi,
+        vector_addr: 0xfffa,
+        b_flag_mask: 0b0010_0000,
+        cpu_cycles: 7,
+    };
+
+    pub(super) const IRQ: Interrupt = Interrupt {
+        itype: InterruptType::Irq,
+        vector_addr: 0xfffe,
+        b_flag_mask: 0b0010_0000,
+        cpu_cycles: 7,
+    };
+
+    // BRK's 7 cycles are already accounted for by its opcode table entry
+    pub(super) const BRK: Interrupt = Interrupt {
+        itype: InterruptType::Brk,
+        vector_addr: 0xfffe,
+        b_flag_mask: 0b0011_0000,
+        cpu_cycles: 0,
+    };
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// BRK was reached while `halt_on_brk` is set
+    Break,
+    /// `CPU::stop` was called, typically from the run callback
+    Requested,
+    /// A KIL/JAM opcode locked up the CPU
+    Jam,
+}
+
 pub struct CPU {
     pub register_a : u8,
     pub register_x : u8,
@@ -24,6 +77,12 @@ pub struct CPU {
     pub status : CpuFlags,
     pub program_count : u16,
     pub stack_pointer: u8,
+    pub cycles: usize,
+    nmi_pending: bool,
+    irq_line: bool,
+    /// Stop the run loop on BRK instead of entering the interrupt handler
+    pub halt_on_brk: bool,
+    stop_requested: bool,
     bus: Bus,
 }
 
@@ -43,37 +102,55 @@ pub enum AddressingMode {
 }
 
 pub trait Mem {
-    fn mem_read(&self, addr: u16) -> u8;
+    fn mem_read(&mut self, addr: u16) -> u8;
+
+    /// Reads without the side effects a real bus access can have (PPUSTATUS
+    /// clearing vblank, the PPUDATA buffer), for decoding operands and tracing
+    fn mem_peek(&self, addr: u16) -> u8;
 
     fn mem_write(&mut self, addr: u16, data: u8);
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos) as u16;
-        let hi = self.mem_read(pos + 1) as u16;
-        (hi << 8) | (lo as u16)
+        let hi = self.mem_read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn mem_peek_u16(&self, pos: u16) -> u16 {
+        let lo = self.mem_peek(pos) as u16;
+        let hi = self.mem_peek(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
     }
 
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xff) as u8;
         self.mem_write(pos, lo);
-        self.mem_write(pos + 1, hi);
+        self.mem_write(pos.wrapping_add(1), hi);
     }
 }
 
 impl Mem for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
 
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.bus.mem_peek(addr)
+    }
+
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data)
     }
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         self.bus.mem_read_u16(pos)
     }
 
+    fn mem_peek_u16(&self, pos: u16) -> u16 {
+        self.bus.mem_peek_u16(pos)
+    }
+
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         self.bus.mem_write_u16(pos, data)
     }
@@ -81,7 +158,7 @@ impl Mem for CPU {
 
 
 impl CPU {
-    pub fn new () -> Self {
+    pub fn new(bus: Bus) -> Self {
         CPU {
             register_a : 0,
             register_x : 0,
@@ -89,101 +166,145 @@ impl CPU {
             stack_pointer:STACK_RESET,
             status : CpuFlags::from_bits_truncate(0b10_0100),
             program_count : 0,
-            bus: Bus::new(),
+            cycles: 0,
+            nmi_pending: false,
+            irq_line: false,
+            halt_on_brk: false,
+            stop_requested: false,
+            bus,
         }
     }
 
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
     pub fn reset (&mut self) {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
+        self.stack_pointer = STACK_RESET;
         self.status = CpuFlags::from_bits_truncate(0b10_0100);
+        self.nmi_pending = false;
         self.program_count = self.mem_read_u16(0xFFFC);
+        // the reset sequence itself takes 7 cycles
+        self.cycles = 7;
+        self.bus.tick(7);
     }
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        for i in 0..(program.len() as u16) {
-            self.mem_write(0x0000 + i, program[i as usize]);
+    /// Copies a raw program into memory at `addr`, bypassing the cartridge
+    pub fn load_at(&mut self, addr: u16, program: &[u8]) {
+        for (i, &byte) in program.iter().enumerate() {
+            self.mem_write(addr.wrapping_add(i as u16), byte);
         }
-        self.mem_write_u16(0xFFFC, 0x0000);
     }
 
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
+    /// Loads a raw program at $0600, the address the test cartridge resets to
+    pub fn load(&mut self, program: Vec<u8>) {
+        self.load_at(0x0600, &program);
+    }
+
+    fn get_operand_address(&self, mode: &AddressingMode) -> (u16, bool) {
+        self.get_absolute_address(mode, self.program_count)
+    }
+
+    /// Resolves the operand stored at `addr` into the effective address and
+    /// whether indexing crossed a page boundary. Operands and zero page
+    /// pointers are peeked, as they never live in registers with read side effects
+    pub fn get_absolute_address(&self, mode: &AddressingMode, addr: u16) -> (u16, bool) {
         match mode {
-            AddressingMode::Immediate => self.program_count,
-            AddressingMode::ZeroPage => self.mem_read(self.program_count) as u16,
-            AddressingMode::Absolute => self.mem_read_u16(self.program_count),
+            AddressingMode::Immediate => (addr, false),
+            AddressingMode::ZeroPage => (self.mem_peek(addr) as u16, false),
+            AddressingMode::Absolute => (self.mem_peek_u16(addr), false),
 
             AddressingMode::ZeroPage_X => {
-                let addr = self.mem_read(self.program_count) as u16;
-                addr.wrapping_add(self.register_x as u16)
+                let pos = self.mem_peek(addr);
+                (pos.wrapping_add(self.register_x) as u16, false)
             }
             AddressingMode::ZeroPage_Y => {
-                let addr = self.mem_read(self.program_count) as u16;
-                addr.wrapping_add(self.register_y as u16)
+                let pos = self.mem_peek(addr);
+                (pos.wrapping_add(self.register_y) as u16, false)
             }
 
             AddressingMode::Absolute_X => {
-                let addr = self.mem_read_u16(self.program_count);
-                addr.wrapping_add(self.register_x as u16)
+                let base = self.mem_peek_u16(addr);
+                let indexed = base.wrapping_add(self.register_x as u16);
+                (indexed, page_cross(base, indexed))
             }
 
             AddressingMode::Absolute_Y => {
-                let addr = self.mem_read_u16(self.program_count);
-                addr.wrapping_add(self.register_y as u16)
+                let base = self.mem_peek_u16(addr);
+                let indexed = base.wrapping_add(self.register_y as u16);
+                (indexed, page_cross(base, indexed))
             }
 
             AddressingMode::Indirect_X => {
-                let base = self.mem_read(self.program_count);
-                let ptr = (base as u8).wrapping_add(self.register_x);
-                let lo = self.mem_read(ptr as u16);
-                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-                ((hi as u16) << 8) | (lo as u16)
+                let base = self.mem_peek(addr);
+                let ptr = base.wrapping_add(self.register_x);
+                let lo = self.mem_peek(ptr as u16);
+                let hi = self.mem_peek(ptr.wrapping_add(1) as u16);
+                (((hi as u16) << 8) | (lo as u16), false)
             }
 
             AddressingMode::Indirect_Y => {
-                let base = self.mem_read(self.program_count);
-                let lo = self.mem_read(base as u16);
-                let hi = self.mem_read(base.wrapping_add(1) as u16);
-                let addr = ((hi as u16) << 8) | (lo as u16);
-                addr.wrapping_add(self.register_y as u16)
+                let base = self.mem_peek(addr);
+                let lo = self.mem_peek(base as u16);
+                let hi = self.mem_peek(base.wrapping_add(1) as u16);
+                let deref_base = ((hi as u16) << 8) | (lo as u16);
+                let deref = deref_base.wrapping_add(self.register_y as u16);
+                (deref, page_cross(deref_base, deref))
             }
 
             AddressingMode::NoneAddressing => panic!("Invalid Addressing Mode")
         }
     }
 
+    /// Runs a raw program from $0600 until it reaches BRK
     pub fn load_and_run (&mut self, program: Vec<u8>) {
         self.load(program);
         self.reset();
+        self.halt_on_brk = true;
         self.run();
     }
 
     fn ldy(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.register_y = value;
         self.update_zero_and_negative_flags(self.register_y);
     }
 
     fn ldx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.register_x = value;
         self.update_zero_and_negative_flags(self.register_x);
     }
 
     fn lda(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.set_register_a(value);
     }
 
     fn sta (&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         self.mem_write(addr, self.register_a);
     }
 
@@ -193,22 +314,31 @@ impl CPU {
     }
 
     fn and(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.set_register_a(self.register_a & value);
     }
 
     fn eor(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.set_register_a(self.register_a ^ value);
     }
 
     fn ora(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
 
         self.set_register_a(self.register_a | value);
     }
@@ -232,14 +362,6 @@ impl CPU {
         }
     }
 
-    fn update_negative_flag (&mut self, result: u8) {
-        if result >> 7 == 1 {
-            self.status.insert(CpuFlags::NEGATIV);
-        } else {
-            self.status.remove(CpuFlags::NEGATIV);
-        }
-    }
-
     fn inx(&mut self) {
         self.register_x = self.register_x.wrapping_add(1);
         self.update_zero_and_negative_flags(self.register_x);
@@ -283,24 +405,34 @@ impl CPU {
     }
 
     fn sbc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
-        self.add_to_register_a(((value as i8).wrapping_neg().wrapping_sub(1)) as u8);
+        if page_cross {
+            self.cycles += 1;
+        }
+        self.sub_from_register_a(value);
+    }
+
+    fn sub_from_register_a(&mut self, data: u8) {
+        self.add_to_register_a(((data as i8).wrapping_neg().wrapping_sub(1)) as u8);
     }
 
     fn adc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
         self.add_to_register_a(value);
     }
 
     fn stack_pop (&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        self.mem_read((STACK as u16) + self.stack_pointer as u16)
+        self.mem_read(STACK + self.stack_pointer as u16)
     }
 
     fn stack_push (&mut self, value: u8) {
-        self.mem_write((STACK as u16) + self.stack_pointer as u16, value);
+        self.mem_write(STACK + self.stack_pointer as u16, value);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1)
     }
 
@@ -328,13 +460,21 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        data = data << 1;
+        data <<= 1;
         self.set_register_a(data)
     }
 
+    /// Operand read of a read-modify-write instruction. The 6502 writes the
+    /// unmodified value back before the result, which some mappers notice
+    fn read_for_modify(&mut self, addr: u16) -> u8 {
+        let value = self.mem_read(addr);
+        self.mem_write(addr, value);
+        value
+    }
+
     fn asl(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
 
         if value >> 7 == 1 {
             self.set_carry_flag();
@@ -342,7 +482,7 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        value = value << 1;
+        value <<= 1;
         self.mem_write(addr, value);
         self.update_zero_and_negative_flags(value);
         value
@@ -357,13 +497,13 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        data = data >> 1;
+        data >>= 1;
         self.set_register_a(data)
     }
 
     fn lsr(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
 
         if value & 1 == 1 {
             self.set_carry_flag();
@@ -371,7 +511,7 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        value = value >> 1;
+        value >>= 1;
         self.mem_write(addr, value);
         self.update_zero_and_negative_flags(value);
         value
@@ -388,17 +528,17 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        data = data << 1;
+        data <<= 1;
         if old_carry {
-            data = data | 1;
+            data |= 1;
         }
 
         self.set_register_a(data);
     }
 
     fn rol(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
         let old_carry = self.status.contains(CpuFlags::CARRY);
 
         if value >> 7 == 1 {
@@ -407,13 +547,13 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        value = value << 1;
+        value <<= 1;
         if old_carry {
-            value = value | 1;
+            value |= 1;
         }
 
-        self.update_negative_flag(value);
         self.mem_write(addr, value);
+        self.update_zero_and_negative_flags(value);
         value
     }
 
@@ -428,17 +568,17 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        data = data >> 1;
+        data >>= 1;
         if old_carry {
-            data = data | 0b1000_0000;
+            data |= 0b1000_0000;
         }
 
         self.set_register_a(data);
     }
 
     fn ror(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
         let old_carry = self.status.contains(CpuFlags::CARRY);
 
         if value & 1 == 1 {
@@ -447,9 +587,9 @@ impl CPU {
             self.clear_carry_flag();
         }
 
-        value = value >> 1;
+        value >>= 1;
         if old_carry {
-            value = value | 0b1000_0000;
+            value |= 0b1000_0000;
         }
 
         self.mem_write(addr, value);
@@ -458,8 +598,8 @@ impl CPU {
     }
 
     fn inc(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
 
         value = value.wrapping_add(1);
         self.mem_write(addr, value);
@@ -478,8 +618,8 @@ impl CPU {
     }
 
     fn dec(&mut self, mode: &AddressingMode) -> u8 {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.read_for_modify(addr);
 
         value = value.wrapping_sub(1);
         self.mem_write(addr, value);
@@ -500,13 +640,14 @@ impl CPU {
     }
 
     fn php (&mut self) {
-        self.stack_push(self.status.bits);
-        self.status.remove(CpuFlags::BREAK);
-        self.status.insert(CpuFlags::BREAK);
+        let mut flags = self.status;
+        flags.insert(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits);
     }
 
     fn bit (&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         let and = self.register_a & value;
 
@@ -521,8 +662,15 @@ impl CPU {
     }
 
     fn compare(&mut self, mode: &AddressingMode, compare_with: u8) {
-        let addr = self.get_operand_address(mode);
+        let (addr, page_cross) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
+        self.compare_value(value, compare_with);
+    }
+
+    fn compare_value(&mut self, value: u8, compare_with: u8) {
         if value <= compare_with {
             self.set_carry_flag();
         } else {
@@ -532,38 +680,219 @@ impl CPU {
         self.update_zero_and_negative_flags(compare_with.wrapping_sub(value));
     }
 
+    /* unofficial opcodes */
+
+    fn nop_read(&mut self, mode: &AddressingMode) {
+        let (addr, page_cross) = self.get_operand_address(mode);
+        self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
+    }
+
+    fn lax(&mut self, mode: &AddressingMode) {
+        let (addr, page_cross) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        if page_cross {
+            self.cycles += 1;
+        }
+        self.set_register_a(value);
+        self.register_x = self.register_a;
+    }
+
+    fn sax(&mut self, mode: &AddressingMode) {
+        let (addr, _) = self.get_operand_address(mode);
+        self.mem_write(addr, self.register_a & self.register_x);
+    }
+
+    fn dcp(&mut self, mode: &AddressingMode) {
+        let value = self.dec(mode);
+        self.compare_value(value, self.register_a);
+    }
+
+    fn isb(&mut self, mode: &AddressingMode) {
+        let value = self.inc(mode);
+        self.sub_from_register_a(value);
+    }
+
+    fn slo(&mut self, mode: &AddressingMode) {
+        let value = self.asl(mode);
+        self.set_register_a(self.register_a | value);
+    }
+
+    fn rla(&mut self, mode: &AddressingMode) {
+        let value = self.rol(mode);
+        self.set_register_a(self.register_a & value);
+    }
+
+    fn sre(&mut self, mode: &AddressingMode) {
+        let value = self.lsr(mode);
+        self.set_register_a(self.register_a ^ value);
+    }
+
+    fn rra(&mut self, mode: &AddressingMode) {
+        let value = self.ror(mode);
+        self.add_to_register_a(value);
+    }
+
+    fn anc(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.status.set(CpuFlags::CARRY, self.status.contains(CpuFlags::NEGATIV));
+    }
+
+    fn alr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.lsr_accumulator();
+    }
+
+    fn arr(&mut self, mode: &AddressingMode) {
+        self.and(mode);
+        self.ror_accumulator();
+
+        let bit_6 = self.register_a & 0b0100_0000 != 0;
+        let bit_5 = self.register_a & 0b0010_0000 != 0;
+        self.status.set(CpuFlags::CARRY, bit_6);
+        self.status.set(CpuFlags::OVERFLOW, bit_6 ^ bit_5);
+    }
+
+    fn axs(&mut self, mode: &AddressingMode) {
+        let (addr, _) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        let and = self.register_a & self.register_x;
+
+        self.status.set(CpuFlags::CARRY, value <= and);
+        self.register_x = and.wrapping_sub(value);
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    // XAA and the immediate LAX are unstable on real hardware, the
+    // "magic" constant below is the value most NES consoles settle on
+    fn xaa(&mut self, mode: &AddressingMode) {
+        let (addr, _) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        self.set_register_a((self.register_a | 0xee) & self.register_x & value);
+    }
+
+    fn lxa(&mut self, mode: &AddressingMode) {
+        let (addr, _) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        self.set_register_a((self.register_a | 0xee) & value);
+        self.register_x = self.register_a;
+    }
+
+    fn las(&mut self, mode: &AddressingMode) {
+        let (addr, page_cross) = self.get_operand_address(mode);
+        let value = self.mem_read(addr) & self.stack_pointer;
+        if page_cross {
+            self.cycles += 1;
+        }
+        self.set_register_a(value);
+        self.register_x = value;
+        self.stack_pointer = value;
+    }
+
+    /// SHA/SHX/SHY/TAS store `data & (high byte of the base address + 1)`,
+    /// when indexing crosses a page that value also replaces the high byte
+    /// of the target address
+    fn unstable_store(&mut self, mode: &AddressingMode, data: u8) {
+        let (addr, page_cross) = self.get_operand_address(mode);
+        let index = match mode {
+            AddressingMode::Absolute_X => self.register_x,
+            _ => self.register_y,
+        };
+        let base = addr.wrapping_sub(index as u16);
+        let value = data & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if page_cross {
+            ((value as u16) << 8) | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        self.mem_write(addr, value);
+    }
+
     fn branch(&mut self, condition: bool) {
         if condition {
+            self.cycles += 1;
+
             let jump: i8 = self.mem_read(self.program_count) as i8;
-            let jump_addr = self.program_count.wrapping_add(jump as u16).wrapping_add(1);
+            let next_addr = self.program_count.wrapping_add(1);
+            let jump_addr = next_addr.wrapping_add(jump as u16);
+
+            if page_cross(next_addr, jump_addr) {
+                self.cycles += 1;
+            }
+
             self.program_count = jump_addr;
         }
     }
 
-    pub fn run(&mut self) {
-        self.run_with_callback(|_| {});
+    /// Signals an edge on the NMI line, serviced before the next instruction
+    pub fn nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Drives the level-triggered IRQ line, serviced while asserted and not masked
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        self.stack_push_u16(self.program_count);
+
+        let mut flag = self.status;
+        flag.set(CpuFlags::BREAK, interrupt.b_flag_mask & 0b0001_0000 != 0);
+        flag.set(CpuFlags::BREAK2, interrupt.b_flag_mask & 0b0010_0000 != 0);
+        self.stack_push(flag.bits);
+
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.cycles += interrupt.cpu_cycles as usize;
+        self.program_count = self.mem_read_u16(interrupt.vector_addr);
+    }
+
+    fn poll_interrupts(&mut self) {
+        if self.bus.poll_nmi() {
+            self.nmi_pending = true;
+        }
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(interrupt::NMI);
+        } else if (self.irq_line || self.bus.irq()) && !self.status.contains(CpuFlags::INTERRUPT_DISABLE) {
+            self.interrupt(interrupt::IRQ);
+        }
+    }
+
+    /// Makes the run loop return before fetching the next instruction
+    pub fn stop(&mut self) {
+        self.stop_requested = true;
+    }
+
+    pub fn run(&mut self) -> StopReason {
+        self.run_with_callback(|_| {})
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F) 
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> StopReason
     where 
         F: FnMut(&mut CPU),
     {
-        let ref opcode: HashMap<u8, &'static opcode::OpCode> = *opcode::OPCODES_MAP;
+        let opcode: &HashMap<u8, &'static opcode::OpCode> = &opcode::OPCODES_MAP;
 
         loop {
+            let start_cycles = self.cycles;
+            self.poll_interrupts();
+
+            callback(self);
+
+            if self.stop_requested {
+                self.stop_requested = false;
+                return StopReason::Requested;
+            }
+
             let code = self.mem_read(self.program_count);
-            self.program_count += 1;
+            self.program_count = self.program_count.wrapping_add(1);
             let program_count_state = self.program_count;
 
-            let opcode = opcode.get(&code).expect(&format!("Code {:x} is not recognized", code));
+            let opcode = opcode.get(&code).unwrap_or_else(|| panic!("Code {:x} is not recognized", code));
 
-            println!("opcode: {:x?}", opcode);
-            println!("program_count: {:x?}", self.program_count);
-            println!("stack_pointer: {:x?}", self.stack_pointer);
-            println!("register_a: {:x?}", self.register_a);
-            println!("register_x: {:x?}", self.register_x);
-            println!("register_y: {:x?}", self.register_y);
-            println!("status: {:x?}", self.status);
             match code {
 
                 /* ADC */
@@ -605,8 +934,12 @@ impl CPU {
 
                 /* BRK */
                 0x00 => {
-                    println!("BRK");
-                    return
+                    if self.halt_on_brk {
+                        return StopReason::Break;
+                    }
+                    // BRK is followed by a padding byte, the handler returns past it
+                    self.program_count = self.program_count.wrapping_add(1);
+                    self.interrupt(interrupt::BRK);
                 }
 
                 /* BVC */
@@ -659,7 +992,7 @@ impl CPU {
                 }
 
                 /* INC */
-                0xe6 | 0xf6 | 0xee | 0xef => {
+                0xe6 | 0xf6 | 0xee | 0xfe => {
                     self.inc(&opcode.mode);
                 }
 
@@ -789,13 +1122,13 @@ impl CPU {
 
                 /* STX */
                 0x86 | 0x96 | 0x8e => {
-                    let addr = self.get_operand_address(&opcode.mode);
+                    let (addr, _) = self.get_operand_address(&opcode.mode);
                     self.mem_write(addr, self.register_x);
                 }
 
                 /* STY */
                 0x84 | 0x94 | 0x8c => {
-                    let addr = self.get_operand_address(&opcode.mode);
+                    let (addr, _) = self.get_operand_address(&opcode.mode);
                     self.mem_write(addr, self.register_y);
                 }
 
@@ -831,16 +1164,104 @@ impl CPU {
                     self.update_zero_and_negative_flags(self.register_a);
                 }
 
-                _ => todo!()
+                /* unofficial NOP */
+                0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => {}
+                0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {}
+                0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c |
+                0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
+                    self.nop_read(&opcode.mode);
+                }
+
+                /* JAM */
+                0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2 => {
+                    self.program_count = self.program_count.wrapping_sub(1);
+                    return StopReason::Jam;
+                }
+
+                /* LAX */
+                0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => self.lax(&opcode.mode),
+                0xab => self.lxa(&opcode.mode),
+
+                /* SAX */
+                0x87 | 0x97 | 0x8f | 0x83 => self.sax(&opcode.mode),
+
+                /* SBC (unofficial) */
+                0xeb => self.sbc(&opcode.mode),
+
+                /* DCP */
+                0xc7 | 0xd7 | 0xcf | 0xdf | 0xdb | 0xc3 | 0xd3 => self.dcp(&opcode.mode),
+
+                /* ISB */
+                0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => self.isb(&opcode.mode),
+
+                /* SLO */
+                0x07 | 0x17 | 0x0f | 0x1f | 0x1b | 0x03 | 0x13 => self.slo(&opcode.mode),
+
+                /* RLA */
+                0x27 | 0x37 | 0x2f | 0x3f | 0x3b | 0x23 | 0x33 => self.rla(&opcode.mode),
+
+                /* SRE */
+                0x47 | 0x57 | 0x4f | 0x5f | 0x5b | 0x43 | 0x53 => self.sre(&opcode.mode),
+
+                /* RRA */
+                0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => self.rra(&opcode.mode),
+
+                /* ANC */
+                0x0b | 0x2b => self.anc(&opcode.mode),
+
+                /* ALR */
+                0x4b => self.alr(&opcode.mode),
+
+                /* ARR */
+                0x6b => self.arr(&opcode.mode),
+
+                /* AXS */
+                0xcb => self.axs(&opcode.mode),
+
+                /* XAA */
+                0x8b => self.xaa(&opcode.mode),
+
+                /* SHA */
+                0x9f | 0x93 => {
+                    self.unstable_store(&opcode.mode, self.register_a & self.register_x);
+                }
+
+                /* SHX */
+                0x9e => self.unstable_store(&opcode.mode, self.register_x),
+
+                /* SHY */
+                0x9c => self.unstable_store(&opcode.mode, self.register_y),
+
+                /* TAS */
+                0x9b => {
+                    self.stack_pointer = self.register_a & self.register_x;
+                    self.unstable_store(&opcode.mode, self.stack_pointer);
+                }
+
+                /* LAS */
+                0xbb => self.las(&opcode.mode),
             }
 
             if program_count_state == self.program_count {
                 self.program_count += (opcode.len - 1) as u16;
             }
 
-            println!("cpu status: {:x}", self.program_count);
-            println!("");
-            callback(self);
+            self.cycles += opcode.cycles as usize;
+            self.bus.tick((self.cycles - start_cycles) as u8);
+
+            if self.bus.take_oam_dma_stall() {
+                self.oam_dma_stall();
+            }
+        }
+    }
+
+    /// OAM DMA holds the CPU for 513 cycles, plus one when it has to wait
+    /// for an odd cycle to line up its reads
+    fn oam_dma_stall(&mut self) {
+        let stall = 513 + (self.cycles & 1);
+        for _ in 0..stall {
+            self.cycles += 1;
+            self.bus.tick(1);
         }
     }
 }
@@ -849,10 +1270,11 @@ impl CPU {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::rom::test::test_rom;
 
     #[test]
     fn test_0xa9_lda_immidiate_load_data() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
         assert_eq!(cpu.register_a, 0x05);
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -861,7 +1283,7 @@ mod test {
 
     #[test]
     fn test_0xa9_lda_zero_flag() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
         assert_eq!(cpu.register_a, 0x00);
         assert!(cpu.status.contains(CpuFlags::ZERO));
@@ -869,7 +1291,7 @@ mod test {
 
     #[test]
     fn test_0xaa_tax() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9,0x05,0xaa, 0x00]);
         assert_eq!(cpu.register_x, 0x05);
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -878,7 +1300,7 @@ mod test {
 
     #[test]
     fn test_5_ops_working_together() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x05, 0xaa, 0xa9, 0x08, 0x00]);
         assert_eq!(cpu.register_a, 0x08);
         assert_eq!(cpu.register_x, 0x05);
@@ -886,7 +1308,7 @@ mod test {
 
     #[test]
     fn test_inx_overflow() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0xff, 0xaa, 0xe8, 0x00]);
 
         assert_eq!(cpu.register_x, 0)
@@ -894,7 +1316,7 @@ mod test {
 
     #[test]
     fn test_lda_from_memory() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.mem_write(0x10, 0x55);
         cpu.load_and_run(vec![0xa5, 0x10, 0x00]);
 
@@ -903,7 +1325,7 @@ mod test {
 
     #[test]
     fn test_0x29_and() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x01, 0x29, 0x2]);
         assert_eq!(cpu.register_a, 0x0);
         cpu.reset();
@@ -913,7 +1335,7 @@ mod test {
 
     #[test]
     fn test_0x49_eor() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x01, 0x49, 0x2]);
         assert_eq!(cpu.register_a, 0x3);
         cpu.reset();
@@ -923,7 +1345,7 @@ mod test {
 
     #[test]
     fn test_0x09_eor() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x01, 0x09, 0x00]);
         assert_eq!(cpu.register_a, 0x1);
         cpu.reset();
@@ -933,7 +1355,7 @@ mod test {
 
     #[test]
     fn test_0xe9_sbc() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x05, 0xe9, 0x03, 0x00]);
         assert_eq!(cpu.register_a, 0x1);
         cpu.reset();
@@ -943,7 +1365,7 @@ mod test {
 
     #[test]
     fn test_0x69_adc() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x0f, 0x69, 0x01, 0x00]);
         assert_eq!(cpu.register_a, 0x0f + 0x1);
         cpu.reset();
@@ -953,14 +1375,14 @@ mod test {
 
     #[test]
     fn test_0x0a_asl() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x0f, 0x0a, 0x00]);
         assert_eq!(cpu.register_a, 0x0f << 1);
     }
 
     #[test]
     fn test_0x4a_lsr() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x0f, 0x4a, 0x00]);
         assert_eq!(cpu.register_a, 0x0f >> 1);
         assert!(cpu.status.contains(CpuFlags::CARRY));
@@ -968,16 +1390,273 @@ mod test {
 
     #[test]
     fn test_0x2a_rol() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x0f, 0x2a, 0x00]);
         assert_eq!(cpu.register_a, 0x0f << 1);
     }
 
     #[test]
     fn test_0x6a_rol() {
-        let mut cpu = CPU::new();
+        let mut cpu = CPU::new(Bus::new(test_rom()));
         cpu.load_and_run(vec![0xa9, 0x0f, 0x6a, 0x00]);
         assert_eq!(cpu.register_a, 0x0f >> 1);
     }
-}
 
+    #[test]
+    fn test_cycles_base() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDA #$05 (2) + TAX (2) on top of the 7 reset cycles
+        cpu.load_and_run(vec![0xa9, 0x05, 0xaa, 0x00]);
+        assert_eq!(cpu.cycles, 7 + 2 + 2);
+    }
+
+    #[test]
+    fn test_cycles_oam_dma() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDA #$02, STA $4014 ends on an odd cycle
+        cpu.load_and_run(vec![0xa9, 0x02, 0x8d, 0x14, 0x40, 0x00]);
+        assert_eq!(cpu.cycles, 7 + 2 + 4 + 514);
+
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDA #$02, STA $00, STA $4014 ends on an even one
+        cpu.load_and_run(vec![0xa9, 0x02, 0x85, 0x00, 0x8d, 0x14, 0x40, 0x00]);
+        assert_eq!(cpu.cycles, 7 + 2 + 3 + 4 + 513);
+    }
+
+    #[test]
+    fn test_cycles_page_cross() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDX #$01, LDA $00FF,X crosses into page $01
+        cpu.load_and_run(vec![0xa2, 0x01, 0xbd, 0xff, 0x00, 0x00]);
+        assert_eq!(cpu.cycles, 7 + 2 + 5);
+    }
+
+    #[test]
+    fn test_cycles_branch_taken() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDA #$00, BEQ +0 is taken without leaving the page
+        cpu.load_and_run(vec![0xa9, 0x00, 0xf0, 0x00, 0x00]);
+        assert_eq!(cpu.cycles, 7 + 2 + 3);
+    }
+
+    #[test]
+    fn test_nmi() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0xa9, 0x05, 0x00]);
+        cpu.reset();
+        cpu.halt_on_brk = true;
+        cpu.nmi();
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(3));
+        assert_eq!(cpu.mem_read(0x01fb), 0b0010_0100);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        assert_eq!(cpu.cycles, 7 + 7 + 2);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // LDA #$80; STA $2000; JMP $0605, the NMI vector points at $0600
+        cpu.load(vec![0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0x06]);
+        cpu.reset();
+        cpu.run_with_callback(|cpu| {
+            if cpu.program_count == 0x0600 && cpu.cycles > 7 {
+                cpu.stop();
+            }
+        });
+
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(3));
+        assert_eq!(cpu.bus().ppu().scanline, 241);
+        assert!(cpu.bus().ppu().dot < 30);
+    }
+
+    #[test]
+    fn test_irq_masked() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0xa9, 0x05, 0x00]);
+        cpu.reset();
+        cpu.halt_on_brk = true;
+        cpu.set_irq(true);
+        cpu.run();
+
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0xa9, 0x05, 0x00]);
+        cpu.reset();
+        cpu.halt_on_brk = true;
+        cpu.status.remove(CpuFlags::INTERRUPT_DISABLE);
+        cpu.set_irq(true);
+        cpu.run_with_callback(|cpu| cpu.set_irq(false));
+
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(3));
+        assert_eq!(cpu.mem_read(0x01fb), 0b0010_0000);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0x00, 0x00]);
+        cpu.reset();
+        let reason = cpu.run_with_callback(|cpu| {
+            if cpu.stack_pointer != STACK_RESET {
+                cpu.stop();
+            }
+        });
+
+        assert_eq!(reason, StopReason::Requested);
+        assert_eq!(cpu.mem_read(0x01fd), 0x06);
+        assert_eq!(cpu.mem_read(0x01fc), 0x02);
+        assert_eq!(cpu.mem_read(0x01fb), 0b0011_0100);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        assert_eq!(cpu.cycles, 7 + 7);
+    }
+
+    #[test]
+    fn test_halt_on_brk() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0xa9, 0x05, 0x00]);
+        cpu.reset();
+        cpu.halt_on_brk = true;
+
+        assert_eq!(cpu.run(), StopReason::Break);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+    }
+
+    #[test]
+    fn test_0x08_php() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load_and_run(vec![0x08, 0x00]);
+        assert_eq!(cpu.mem_read(0x01fd), 0b0011_0100);
+        assert!(!cpu.status.contains(CpuFlags::BREAK));
+    }
+
+    #[test]
+    fn test_reset_jumps_through_cartridge_vector() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.reset();
+        assert_eq!(cpu.program_count, 0x0600);
+        assert_eq!(cpu.stack_pointer, STACK_RESET);
+        assert_eq!(cpu.status.bits(), 0b0010_0100);
+    }
+
+    #[test]
+    fn test_0xa7_lax() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x10, 0x85);
+        cpu.load_and_run(vec![0xa7, 0x10, 0x00]);
+        assert_eq!(cpu.register_a, 0x85);
+        assert_eq!(cpu.register_x, 0x85);
+        assert!(cpu.status.contains(CpuFlags::NEGATIV));
+    }
+
+    #[test]
+    fn test_0x87_sax() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load_and_run(vec![0xa9, 0xf0, 0xa2, 0x3c, 0x87, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0x30);
+    }
+
+    #[test]
+    fn test_0xc7_dcp() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x10, 0x06);
+        cpu.load_and_run(vec![0xa9, 0x05, 0xc7, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0x05);
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_0x07_slo() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x10, 0x81);
+        cpu.load_and_run(vec![0xa9, 0x01, 0x07, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0x02);
+        assert_eq!(cpu.register_a, 0x03);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_0xcb_axs() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load_and_run(vec![0xa9, 0x0f, 0xa2, 0x07, 0xcb, 0x02, 0x00]);
+        assert_eq!(cpu.register_x, 0x05);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_jam() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.load(vec![0xa9, 0x05, 0x02, 0x00]);
+        cpu.reset();
+        assert_eq!(cpu.run(), StopReason::Jam);
+        assert_eq!(cpu.register_a, 0x05);
+        assert_eq!(cpu.program_count, 0x0602);
+    }
+
+    #[test]
+    fn test_zero_page_x_wraps() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x0f, 0x55);
+        cpu.load_and_run(vec![0xa2, 0x10, 0xb5, 0xff, 0x00]);
+        assert_eq!(cpu.register_a, 0x55);
+    }
+
+    #[test]
+    fn test_zero_page_y_wraps() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x0f, 0x55);
+        cpu.load_and_run(vec![0xa0, 0x10, 0xb6, 0xff, 0x00]);
+        assert_eq!(cpu.register_x, 0x55);
+    }
+
+    #[test]
+    fn test_0xbe_ldx_absolute_y() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x0100, 0x42);
+        cpu.load_and_run(vec![0xa0, 0x01, 0xbe, 0xff, 0x00, 0x00]);
+        assert_eq!(cpu.register_x, 0x42);
+        // LDY #$01 (2) + LDX $00FF,Y crossing into page $01 (5)
+        assert_eq!(cpu.cycles, 7 + 2 + 5);
+    }
+
+    #[test]
+    fn test_0x26_rol_zero_flag() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x10, 0x80);
+        cpu.load_and_run(vec![0x18, 0x26, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_mem_read_u16_wraps() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        cpu.mem_write(0x0000, 0x12);
+        // the low byte is the top of the IRQ vector, the high byte RAM at $0000
+        assert_eq!(cpu.mem_read_u16(0xffff), 0x1206);
+    }
+
+    #[test]
+    fn test_program_counter_wraps() {
+        let mut cpu = CPU::new(Bus::new(test_rom()));
+        // $FFFF holds $06, ASL zero page, whose operand is fetched from $0000
+        cpu.mem_write(0x0000, 0x10);
+        cpu.mem_write(0x0001, 0x00);
+        cpu.mem_write(0x10, 0x01);
+        cpu.program_count = 0xffff;
+        cpu.halt_on_brk = true;
+        assert_eq!(cpu.run(), StopReason::Break);
+        assert_eq!(cpu.mem_read(0x10), 0x02);
+        assert_eq!(cpu.program_count, 0x0002);
+    }
+}
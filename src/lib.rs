@@ -0,0 +1,15 @@
+pub mod audio;
+pub mod bus;
+pub mod cpu;
+pub mod mapper;
+pub mod opcode;
+pub mod ppu;
+pub mod rom;
+pub mod save;
+pub mod trace;
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate bitflags;
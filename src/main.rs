@@ -1,21 +1,24 @@
-pub mod cpu;
-pub mod opcode;
-pub mod bus;
-pub mod rom;
-use cpu::Mem;
-use cpu::CPU;
+use nes::audio;
+use nes::bus::Bus;
+use nes::cpu::Mem;
+use nes::cpu::CPU;
+use nes::ppu::frame;
+use nes::rom::Rom;
+use nes::save::SaveFile;
 use rand::Rng;
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
 use sdl2::EventPump;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
 
-#[macro_use]
-extern crate lazy_static;
-
-#[macro_use]
-extern crate bitflags;
+/// How often battery backed RAM is written out while the game runs
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
 
 fn color(byte: u8) -> Color {
     match byte {
@@ -35,7 +38,7 @@ fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     let mut frame_idx = 0;
     let mut update = false;
     for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
+        let color_idx = cpu.mem_peek(i as u16);
         let (b1, b2, b3) = color(color_idx).rgb();
         if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
             frame[frame_idx] = b1;
@@ -48,22 +51,23 @@ fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
     update
 }
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
+// W/A/S/D only mean something to the snake game, which reads them from $ff
+fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump, snake: bool) {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                std::process::exit(0)
+                cpu.stop()
             },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
+            Event::KeyDown { keycode: Some(Keycode::W), .. } if snake => {
                 cpu.mem_write(0xff, 0x77);
             },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
+            Event::KeyDown { keycode: Some(Keycode::S), .. } if snake => {
                 cpu.mem_write(0xff, 0x73);
             },
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => {
+            Event::KeyDown { keycode: Some(Keycode::A), .. } if snake => {
                 cpu.mem_write(0xff, 0x61);
             },
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => {
+            Event::KeyDown { keycode: Some(Keycode::D), .. } if snake => {
                 cpu.mem_write(0xff, 0x64);
             }
             _ => {/* do nothing */}
@@ -71,23 +75,66 @@ fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
     }
 }
 
+// The snake game is a raw program living in RAM at $0600, the cartridge only
+// has to provide a reset vector pointing there
+fn snake_cartridge() -> Rom {
+    let mut raw = vec![0x4e, 0x45, 0x53, 0x1a, 0x01, 0x00, 0x00, 0x00];
+    raw.resize(16, 0);
+
+    let mut prg_rom = vec![0; 0x4000];
+    prg_rom[0x3ffc] = 0x00;
+    prg_rom[0x3ffd] = 0x06;
+    raw.extend(prg_rom);
+
+    Rom::new(&raw).unwrap()
+}
+
+fn flush_save(save_file: &mut Option<SaveFile>, bus: &Bus) {
+    if let Some(save) = save_file {
+        if let Err(err) = save.flush(bus) {
+            eprintln!("could not write {}: {}", save.path().display(), err);
+        }
+    }
+}
+
 fn main() {
+    // an iNES file given on the command line replaces the built-in snake game
+    let rom_path = std::env::args().nth(1).map(PathBuf::from);
+
+    // ROMs show the PPU picture, the snake game its 32x32 pixels at $0200
+    let (title, width, height, scale) = match &rom_path {
+        Some(_) => ("NES", frame::WIDTH as u32, frame::HEIGHT as u32, 3.0),
+        None => ("Snake game", 32, 32, 10.0),
+    };
+
     // init sdl2
     
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
+        .window(title, (width as f32 * scale) as u32, (height as f32 * scale) as u32)
         .position_centered()
         .build().unwrap();
 
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
+
+    // audio is optional, the emulator keeps running silently without a device
+    let audio_queue = sdl_context.audio().ok().and_then(|audio_subsystem| {
+        let spec = AudioSpecDesired {
+            freq: Some(audio::SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &spec).ok()?;
+        queue.resume();
+        Some(queue)
+    });
+    canvas.set_scale(scale, scale).unwrap();
 
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
+        .create_texture_target(PixelFormatEnum::RGB24, width, height).unwrap();
 
     let game_code = vec![
         0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
@@ -113,19 +160,71 @@ fn main() {
         0x60, 0xa6, 0xff, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
     ];
 
+    let cartridge = match &rom_path {
+        Some(path) => {
+            let raw = fs::read(path).unwrap();
+            Rom::new(&raw).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+        }
+        None => snake_cartridge(),
+    };
+
     //load the game
-    let mut cpu = CPU::new();
-    cpu.load(game_code);
+    let mut cpu = CPU::new(Bus::new(cartridge));
+    if rom_path.is_none() {
+        cpu.load(game_code);
+    }
     cpu.reset();
 
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
+    let mut save_file = rom_path.as_deref().map(SaveFile::for_rom);
+    if let Some(save) = &mut save_file {
+        if let Err(err) = save.load(cpu.bus_mut()) {
+            eprintln!("could not load {}: {}", save.path().display(), err);
+        }
+    }
+    let mut last_save = Instant::now();
+
+    let mut screen_state = [0_u8; 32 * 3 * 32];
+    let mut frame_rgb = vec![0_u8; frame::WIDTH * frame::HEIGHT * 3];
     let mut rng = rand::thread_rng();
 
     // run the game cycle
-    cpu.run_with_callback(move |cpu| {
-        handle_user_input(cpu, &mut event_pump);
+    let snake = rom_path.is_none();
+    cpu.run_with_callback(|cpu| {
+        // ROMs are serviced once per frame, the snake game after every
+        // instruction as it has no picture of its own to wait for
+        if !snake && !cpu.bus_mut().take_frame_complete() {
+            return;
+        }
 
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
+        handle_user_input(cpu, &mut event_pump, snake);
+
+        // the snake game reads its random numbers from $fe
+        if snake {
+            cpu.mem_write(0xfe, rng.gen_range(1, 16));
+        }
+
+        let samples = cpu.bus_mut().take_audio_samples();
+        if let Some(queue) = &audio_queue {
+            if !samples.is_empty() {
+                queue.queue(&samples);
+            }
+        }
+
+        if last_save.elapsed() >= SAVE_INTERVAL {
+            flush_save(&mut save_file, cpu.bus());
+            last_save = Instant::now();
+        }
+
+        if !snake {
+            // presenting waits for vsync, which paces the emulation
+            cpu.bus().ppu().frame.to_rgb(&mut frame_rgb);
+            texture.update(None, &frame_rgb, frame::WIDTH * 3).unwrap();
+
+            canvas.copy(&texture, None, None).unwrap();
+
+            canvas.present();
+            return;
+        }
 
         if read_screen_state(cpu, &mut screen_state) {
             texture.update(None, &screen_state, 32 * 3).unwrap();
@@ -137,4 +236,6 @@ fn main() {
 
         std::thread::sleep(std::time::Duration::new(0, 70_000));
     });
+
+    flush_save(&mut save_file, cpu.bus());
 }
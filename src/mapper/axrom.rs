@@ -0,0 +1,117 @@
+use super::DiscreteMemory;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/// Mapper 7: AxROM. A switchable 32K PRG bank and a register bit picking
+/// which 1K of VRAM all four nametables show
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    memory: DiscreteMemory,
+    bus_conflicts: bool,
+    register: u8,
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        let memory = DiscreteMemory::new(&rom);
+
+        Axrom {
+            // only ANROM has bus conflicts, AOROM and most dumps don't
+            bus_conflicts: super::has_bus_conflicts(&rom, false),
+            prg_rom: rom.prg_rom,
+            memory,
+            register: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = (self.register & 0b111) as usize;
+        (bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len()
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.memory.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => {
+                self.register = if self.bus_conflicts {
+                    super::bus_conflict(&self.prg_rom, self.prg_offset(addr), data)
+                } else {
+                    data
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.memory.read_chr(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.memory.write_chr(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.register & 0x10 == 0 {
+            Mirroring::SingleScreenLower
+        } else {
+            Mirroring::SingleScreenUpper
+        }
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.memory.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.memory.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::{banks, cartridge};
+
+    fn axrom(submapper: u8) -> Axrom {
+        Axrom::new(cartridge(submapper, banks(8, PRG_BANK_SIZE, 0), vec![]))
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut axrom = axrom(0);
+        axrom.cpu_write(0x8000, 5);
+        assert_eq!(axrom.cpu_read(0x8000), 5);
+        assert_eq!(axrom.cpu_read(0xFFFF), 5);
+    }
+
+    #[test]
+    fn test_single_screen_mirroring() {
+        let mut axrom = axrom(0);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+        axrom.cpu_write(0x8000, 0x10);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_anrom_bus_conflict() {
+        let mut axrom = axrom(2);
+        // bank 0 is all zeroes, so nothing gets through
+        axrom.cpu_write(0x8000, 0x13);
+        assert_eq!(axrom.cpu_read(0x8000), 0);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+    }
+}
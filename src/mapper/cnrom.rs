@@ -0,0 +1,107 @@
+use super::DiscreteMemory;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 3: CNROM. Fixed 16K or 32K PRG like NROM, with a switchable 8K
+/// CHR ROM bank
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    memory: DiscreteMemory,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(rom: Rom) -> Self {
+        let memory = DiscreteMemory::new(&rom);
+
+        Cnrom {
+            bus_conflicts: super::has_bus_conflicts(&rom, true),
+            prg_rom: rom.prg_rom,
+            memory,
+            mirroring: rom.screen_mirroring,
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_bank as usize * CHR_BANK_SIZE + addr as usize
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(addr),
+            0x8000..=0xFFFF => {
+                let addr = (addr - 0x8000) as usize;
+                self.prg_rom[addr % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.memory.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => {
+                self.chr_bank = if self.bus_conflicts {
+                    super::bus_conflict(&self.prg_rom, (addr - 0x8000) as usize, data)
+                } else {
+                    data
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.memory.read_chr(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.memory.write_chr(self.chr_offset(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.memory.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.memory.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::{banks, cartridge};
+
+    fn cnrom(submapper: u8) -> Cnrom {
+        let chr_rom = banks(4, CHR_BANK_SIZE, 0);
+        Cnrom::new(cartridge(submapper, vec![0x02; 0x8000], chr_rom))
+    }
+
+    #[test]
+    fn test_chr_banking() {
+        let mut cnrom = cnrom(1);
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 3);
+        assert_eq!(cnrom.ppu_read(0x1fff), 3);
+    }
+
+    #[test]
+    fn test_bus_conflict() {
+        let mut cnrom = cnrom(2);
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 2);
+    }
+}
@@ -0,0 +1,475 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+/// The 5B runs at half the CPU clock and divides that by 8 again
+const AUDIO_PRESCALER: u8 = 16;
+const ENVELOPE_STEPS: u8 = 32;
+
+/// Mapper 69: Sunsoft FME-7, and the 5B with its AY-3-8910 style audio.
+/// Registers are written through a command port at $8000 and a parameter
+/// port at $A000
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    command: u8,
+    chr_banks: [u8; 8],
+    /// $6000 bank, with RAM select (bit 6) and RAM enable (bit 7)
+    prg_bank_6000: u8,
+    prg_banks: [u8; 3],
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Fme7 {
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_bank_6000: 0,
+            prg_banks: [0; 3],
+            mirroring: rom.screen_mirroring,
+            irq_enabled: false,
+            counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5b::default(),
+        }
+    }
+
+    fn prg_rom_offset(&self, bank: u8, addr: u16) -> usize {
+        (bank as usize * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        (bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn prg_ram_selected(&self) -> bool {
+        self.prg_bank_6000 & 0x40 != 0
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank_6000 & 0xc0 == 0xc0
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0..=7 => self.chr_banks[self.command as usize] = data,
+            8 => self.prg_bank_6000 = data,
+            9..=0xb => self.prg_banks[(self.command - 9) as usize] = data & 0x3f,
+            0xc => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xd => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xe => self.irq_counter = (self.irq_counter & 0xff00) | data as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00ff) | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => super::read_prg_ram(&self.prg_ram, addr),
+            0x6000..=0x7FFF if self.prg_ram_selected() => 0,
+            0x6000..=0x7FFF => self.prg_rom[self.prg_rom_offset(self.prg_bank_6000 & 0x3f, addr)],
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE];
+                self.prg_rom[self.prg_rom_offset(bank, addr)]
+            }
+            0xE000..=0xFFFF => {
+                let last = ((self.prg_rom.len() / PRG_BANK_SIZE).max(1) - 1) as u8;
+                self.prg_rom[self.prg_rom_offset(last, addr)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                super::write_prg_ram(&mut self.prg_ram, addr, data);
+            }
+            0x8000..=0x9FFF => self.command = data & 0x0f,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.address = data & 0x0f,
+            0xE000..=0xFFFF => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        if self.counter_enabled {
+            for _ in 0..cycles {
+                self.irq_counter = self.irq_counter.wrapping_sub(1);
+                if self.irq_counter == 0xffff && self.irq_enabled {
+                    self.irq_pending = true;
+                }
+            }
+        }
+
+        self.audio.tick(cycles);
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+/// Three square wave channels sharing one noise generator and one envelope
+struct Sunsoft5b {
+    address: u8,
+    prescaler: u8,
+
+    tone_periods: [u16; 3],
+    tone_counters: [u16; 3],
+    tone_high: [bool; 3],
+
+    noise_period: u8,
+    noise_counter: u8,
+    /// Noise runs at half the tone rate
+    noise_half: bool,
+    noise_lfsr: u32,
+
+    /// Tone disable bits 0-2, noise disable bits 3-5
+    mixer: u8,
+    /// Volume in bits 0-3, bit 4 hands the channel to the envelope
+    volumes: [u8; 3],
+
+    envelope_period: u16,
+    envelope_counter: u16,
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Default for Sunsoft5b {
+    fn default() -> Self {
+        Sunsoft5b {
+            address: 0,
+            prescaler: 0,
+            tone_periods: [0; 3],
+            tone_counters: [0; 3],
+            tone_high: [false; 3],
+            noise_period: 0,
+            noise_counter: 0,
+            noise_half: false,
+            noise_lfsr: 1,
+            mixer: 0,
+            volumes: [0; 3],
+            envelope_period: 0,
+            envelope_counter: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: false,
+        }
+    }
+}
+
+impl Sunsoft5b {
+    fn write(&mut self, data: u8) {
+        match self.address {
+            register @ (0 | 2 | 4) => {
+                let channel = register as usize / 2;
+                self.tone_periods[channel] = (self.tone_periods[channel] & 0x0f00) | data as u16;
+            }
+            register @ (1 | 3 | 5) => {
+                let channel = register as usize / 2;
+                self.tone_periods[channel] =
+                    (self.tone_periods[channel] & 0x00ff) | (data as u16 & 0x0f) << 8;
+            }
+            6 => self.noise_period = data & 0x1f,
+            7 => self.mixer = data,
+            register @ 8..=10 => self.volumes[register as usize - 8] = data & 0x1f,
+            11 => self.envelope_period = (self.envelope_period & 0xff00) | data as u16,
+            12 => self.envelope_period = (self.envelope_period & 0x00ff) | (data as u16) << 8,
+            13 => {
+                self.envelope_shape = data & 0x0f;
+                self.envelope_step = 0;
+                self.envelope_attack = data & 0x04 != 0;
+                self.envelope_holding = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.prescaler += 1;
+            if self.prescaler < AUDIO_PRESCALER {
+                continue;
+            }
+            self.prescaler = 0;
+
+            for channel in 0..3 {
+                self.tone_counters[channel] += 1;
+                if self.tone_counters[channel] >= self.tone_periods[channel].max(1) {
+                    self.tone_counters[channel] = 0;
+                    self.tone_high[channel] = !self.tone_high[channel];
+                }
+            }
+
+            self.noise_half = !self.noise_half;
+            if self.noise_half {
+                self.noise_counter += 1;
+                if self.noise_counter >= self.noise_period.max(1) {
+                    self.noise_counter = 0;
+                    let feedback = (self.noise_lfsr ^ (self.noise_lfsr >> 3)) & 1;
+                    self.noise_lfsr = (self.noise_lfsr >> 1) | (feedback << 16);
+                }
+            }
+
+            self.envelope_counter += 1;
+            if self.envelope_counter >= self.envelope_period.max(1) {
+                self.envelope_counter = 0;
+                self.step_envelope();
+            }
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        self.envelope_step += 1;
+        if self.envelope_step < ENVELOPE_STEPS {
+            return;
+        }
+
+        let shape = self.envelope_shape;
+        let alternate = shape & 0x02 != 0;
+        if shape & 0x08 == 0 {
+            // no continue: drop to silence and stay there
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+            self.envelope_step = ENVELOPE_STEPS - 1;
+        } else if shape & 0x01 != 0 {
+            self.envelope_holding = true;
+            self.envelope_attack ^= alternate;
+            self.envelope_step = ENVELOPE_STEPS - 1;
+        } else {
+            self.envelope_attack ^= alternate;
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            ENVELOPE_STEPS - 1 - self.envelope_step
+        }
+    }
+
+    /// 32 logarithmic levels, 1.5dB apart
+    fn amplitude(level: u8) -> f32 {
+        if level == 0 {
+            0.0
+        } else {
+            10f32.powf((level as f32 - 31.0) * 1.5 / 20.0)
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let noise_high = self.noise_lfsr & 1 != 0;
+        let sum: f32 = (0..3)
+            .map(|channel| {
+                let tone = self.tone_high[channel] || self.mixer & (1 << channel) != 0;
+                let noise = noise_high || self.mixer & (8 << channel) != 0;
+                if !(tone && noise) {
+                    return 0.0;
+                }
+
+                let volume = self.volumes[channel];
+                let level = if volume & 0x10 != 0 {
+                    self.envelope_level()
+                } else if volume == 0 {
+                    0
+                } else {
+                    volume * 2 + 1
+                };
+                Self::amplitude(level)
+            })
+            .sum();
+        sum / 3.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn fme7() -> Fme7 {
+        let mut rom = test_rom();
+        rom.mapper = 69;
+        rom.prg_rom = (0..32)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..64)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        Fme7::new(rom)
+    }
+
+    fn command(fme7: &mut Fme7, command: u8, parameter: u8) {
+        fme7.cpu_write(0x8000, command);
+        fme7.cpu_write(0xA000, parameter);
+    }
+
+    fn audio(fme7: &mut Fme7, register: u8, data: u8) {
+        fme7.cpu_write(0xC000, register);
+        fme7.cpu_write(0xE000, data);
+    }
+
+    #[test]
+    fn test_banking() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 3, 17);
+        command(&mut fme7, 9, 4);
+        command(&mut fme7, 10, 5);
+        command(&mut fme7, 11, 6);
+        assert_eq!(fme7.ppu_read(0x0c00), 17);
+        assert_eq!(fme7.cpu_read(0x8000), 4);
+        assert_eq!(fme7.cpu_read(0xA000), 5);
+        assert_eq!(fme7.cpu_read(0xC000), 6);
+        assert_eq!(fme7.cpu_read(0xE000), 31);
+
+        command(&mut fme7, 12, 2);
+        assert_eq!(fme7.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn test_prg_smaller_than_a_bank() {
+        let mut rom = test_rom();
+        rom.mapper = 69;
+        rom.prg_rom = vec![0x42; 0x1000];
+        let fme7 = Fme7::new(rom);
+        assert_eq!(fme7.cpu_peek(0xE000), 0x42);
+        assert_eq!(fme7.cpu_peek(0xFFFF), 0x42);
+    }
+
+    #[test]
+    fn test_6000_bank() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 8, 7);
+        assert_eq!(fme7.cpu_read(0x6000), 7);
+
+        // RAM selected but disabled reads open bus
+        command(&mut fme7, 8, 0x40);
+        fme7.cpu_write(0x6000, 0x55);
+        assert_eq!(fme7.cpu_read(0x6000), 0);
+
+        command(&mut fme7, 8, 0xc0);
+        fme7.cpu_write(0x6000, 0x55);
+        assert_eq!(fme7.cpu_read(0x6000), 0x55);
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut fme7 = fme7();
+        command(&mut fme7, 14, 2);
+        command(&mut fme7, 15, 0);
+        command(&mut fme7, 13, 0x81);
+
+        fme7.cpu_tick(2);
+        assert!(!fme7.irq());
+        fme7.cpu_tick(1);
+        assert!(fme7.irq());
+
+        command(&mut fme7, 13, 0x81);
+        assert!(!fme7.irq());
+    }
+
+    #[test]
+    fn test_tone() {
+        let mut fme7 = fme7();
+        // channel A only, period 2, volume 15
+        audio(&mut fme7, 0, 2);
+        audio(&mut fme7, 7, 0b0011_1110);
+        audio(&mut fme7, 8, 15);
+        assert_eq!(fme7.audio_output(), 0.0);
+
+        fme7.cpu_tick(16);
+        assert_eq!(fme7.audio_output(), 0.0);
+        fme7.cpu_tick(16);
+        assert_eq!(fme7.audio_output(), 1.0 / 3.0);
+        fme7.cpu_tick(32);
+        assert_eq!(fme7.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_envelope_hold() {
+        let mut fme7 = fme7();
+        // tones and noise off, channel A on the envelope
+        audio(&mut fme7, 7, 0b0011_1111);
+        audio(&mut fme7, 8, 0x10);
+        audio(&mut fme7, 11, 1);
+        // continue, attack, hold: ramp up and stay at the top
+        audio(&mut fme7, 13, 0b1101);
+        assert_eq!(fme7.audio_output(), 0.0);
+
+        for _ in 0..40 {
+            fme7.cpu_tick(16);
+        }
+        assert_eq!(fme7.audio_output(), 1.0 / 3.0);
+    }
+}
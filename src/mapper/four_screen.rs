@@ -0,0 +1,133 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+
+/// Extra VRAM on the cartridge, the console only has room for two tables
+const VRAM_SIZE: usize = 0x800;
+const NAMETABLE_SIZE: u16 = 0x400;
+
+/// Four-screen cartridges carry 2K of VRAM for the third and fourth
+/// nametables, the first two stay in the console's. Wraps any board whose
+/// header asks for it
+pub struct FourScreen {
+    board: Box<dyn Mapper>,
+    vram: [u8; VRAM_SIZE],
+}
+
+impl FourScreen {
+    pub fn new(board: Box<dyn Mapper>) -> Self {
+        FourScreen {
+            board,
+            vram: [0; VRAM_SIZE],
+        }
+    }
+
+    /// Offset into the cartridge VRAM, `None` for the console's tables
+    fn vram_index(addr: u16) -> Option<usize> {
+        let addr = addr & 0x0fff;
+        if addr < 2 * NAMETABLE_SIZE {
+            None
+        } else {
+            Some((addr - 2 * NAMETABLE_SIZE) as usize)
+        }
+    }
+}
+
+impl Mapper for FourScreen {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.board.cpu_read(addr)
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        self.board.cpu_peek(addr)
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.board.cpu_write(addr, data);
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.board.ppu_read(addr)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.board.ppu_write(addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
+    }
+
+    fn nametable_read(&mut self, addr: u16) -> Option<u8> {
+        self.board
+            .nametable_read(addr)
+            .or_else(|| Self::vram_index(addr).map(|index| self.vram[index]))
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        if self.board.nametable_write(addr, data) {
+            return true;
+        }
+        match Self::vram_index(addr) {
+            Some(index) => {
+                self.vram[index] = data;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, data: u8) {
+        self.board.ppu_register_write(addr, data);
+    }
+
+    fn sprite_fetch(&mut self, active: bool) {
+        self.board.sprite_fetch(active);
+    }
+
+    fn irq(&self) -> bool {
+        self.board.irq()
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        self.board.cpu_tick(cycles);
+    }
+
+    fn scanline_tick(&mut self) {
+        self.board.scanline_tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.board.audio_output()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.board.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.board.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper;
+    use crate::rom::test::test_rom;
+
+    #[test]
+    fn test_upper_tables_on_cartridge() {
+        let mut rom = test_rom();
+        rom.screen_mirroring = Mirroring::FourScreen;
+        let mut board = mapper::new(rom);
+
+        assert!(!board.nametable_write(0x2005, 1));
+        assert!(!board.nametable_write(0x2405, 2));
+        assert!(board.nametable_write(0x2805, 3));
+        assert!(board.nametable_write(0x2c05, 4));
+
+        assert_eq!(board.nametable_read(0x2005), None);
+        assert_eq!(board.nametable_read(0x2805), Some(3));
+        assert_eq!(board.nametable_read(0x3c05), Some(4));
+    }
+}
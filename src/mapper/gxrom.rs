@@ -0,0 +1,105 @@
+use super::DiscreteMemory;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+/// Mapper 66: GxROM. One register selecting a 32K PRG bank (bits 4-5) and
+/// an 8K CHR bank (bits 0-1)
+pub struct Gxrom {
+    prg_rom: Vec<u8>,
+    memory: DiscreteMemory,
+    mirroring: Mirroring,
+    register: u8,
+}
+
+impl Gxrom {
+    pub fn new(rom: Rom) -> Self {
+        let memory = DiscreteMemory::new(&rom);
+
+        Gxrom {
+            prg_rom: rom.prg_rom,
+            memory,
+            mirroring: rom.screen_mirroring,
+            register: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = ((self.register >> 4) & 0b11) as usize;
+        (bank * PRG_BANK_SIZE + (addr - 0x8000) as usize) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = (self.register & 0b11) as usize;
+        bank * CHR_BANK_SIZE + addr as usize
+    }
+}
+
+impl Mapper for Gxrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.memory.write_prg_ram(addr, data),
+            // the board always has bus conflicts
+            0x8000..=0xFFFF => {
+                self.register = super::bus_conflict(&self.prg_rom, self.prg_offset(addr), data);
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.memory.read_chr(self.chr_offset(addr))
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.memory.write_chr(self.chr_offset(addr), data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.memory.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.memory.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::{banks, cartridge};
+
+    #[test]
+    fn test_banking() {
+        let mut prg_rom = banks(4, PRG_BANK_SIZE, 0xf0);
+        // keep the register lines high so writes go through
+        for bank in 0..4 {
+            prg_rom[bank * PRG_BANK_SIZE + 0x7fff] = 0xff;
+        }
+        let mut gxrom = Gxrom::new(cartridge(0, prg_rom, banks(4, CHR_BANK_SIZE, 0)));
+
+        gxrom.cpu_write(0xFFFF, 0x12);
+        assert_eq!(gxrom.cpu_read(0x8000), 0xf1);
+        assert_eq!(gxrom.ppu_read(0x0000), 2);
+
+        // bank 1 holds 0xf1, which masks bit 1 away
+        gxrom.cpu_write(0x8000, 0x33);
+        assert_eq!(gxrom.cpu_read(0x8000), 0xf3);
+        assert_eq!(gxrom.ppu_read(0x0000), 1);
+    }
+}
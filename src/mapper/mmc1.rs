@@ -0,0 +1,301 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+/// Boards with 512K PRG (SUROM) use CHR bank bit 4 to pick the 256K half
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+/// Mapper 1: MMC1 (SxROM). Registers are loaded serially through a 5 bit
+/// shift register, one bit per write to $8000-$FFFF
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+
+    /// The MMC1 ignores a write on the cycle right after another one, which
+    /// only read-modify-write instructions can do
+    written_this_instruction: bool,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Mmc1 {
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            shift_register: 0,
+            shift_count: 0,
+            // power on with the last bank fixed at $C000
+            control: 0x0c,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            written_this_instruction: false,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0x10 == 0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let outer = if self.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            (self.chr_bank_0 as usize & 0x10) >> 4
+        } else {
+            0
+        };
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let last = (PRG_OUTER_BANK_SIZE.min(self.prg_rom.len()) / PRG_BANK_SIZE).max(1) - 1;
+
+        let slot = ((addr - 0x8000) as usize) / PRG_BANK_SIZE;
+        let bank = match (self.control >> 2) & 0b11 {
+            0 | 1 => (bank & !1) + slot,
+            // fix the first bank at $8000
+            2 if slot == 0 => 0,
+            2 => bank,
+            // fix the last bank at $C000
+            _ if slot == 0 => bank,
+            _ => last,
+        };
+
+        let offset = outer * PRG_OUTER_BANK_SIZE
+            + bank * PRG_BANK_SIZE
+            + (addr as usize & (PRG_BANK_SIZE - 1));
+        offset % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let slot = addr as usize / CHR_BANK_SIZE;
+        let bank = if self.control & 0x10 == 0 {
+            (self.chr_bank_0 as usize & !1) + slot
+        } else if slot == 0 {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+
+        (bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => super::read_prg_ram(&self.prg_ram, addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                super::write_prg_ram(&mut self.prg_ram, addr, data);
+            }
+            0x8000..=0xFFFF => {
+                if self.written_this_instruction {
+                    return;
+                }
+                self.written_this_instruction = true;
+
+                if data & 0x80 != 0 {
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0c;
+                    return;
+                }
+
+                self.shift_register |= (data & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(addr, self.shift_register);
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn cpu_tick(&mut self, _cycles: u8) {
+        self.written_this_instruction = false;
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+    use crate::rom::test::test_rom;
+
+    /// Every bank is filled with its own number
+    fn mmc1_rom(prg_banks: usize, chr_banks: usize) -> Rom {
+        let mut rom = test_rom();
+        rom.mapper = 1;
+        rom.prg_rom = (0..prg_banks)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..chr_banks)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        rom
+    }
+
+    fn mmc1(prg_banks: usize, chr_banks: usize) -> Mmc1 {
+        Mmc1::new(mmc1_rom(prg_banks, chr_banks))
+    }
+
+    fn write_serial(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.cpu_write(addr, (value >> bit) & 1);
+            mmc1.cpu_tick(2);
+        }
+    }
+
+    #[test]
+    fn test_power_on_fixes_last_bank() {
+        let mmc1 = mmc1(8, 2);
+        assert_eq!(mmc1.cpu_peek(0x8000), 0);
+        assert_eq!(mmc1.cpu_peek(0xC000), 7);
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let mut mmc1 = mmc1(8, 2);
+
+        write_serial(&mut mmc1, 0xE000, 3);
+        assert_eq!(mmc1.cpu_read(0x8000), 3);
+        assert_eq!(mmc1.cpu_read(0xC000), 7);
+
+        // fix first bank at $8000, switch $C000
+        write_serial(&mut mmc1, 0x8000, 0b0_1000);
+        assert_eq!(mmc1.cpu_read(0x8000), 0);
+        assert_eq!(mmc1.cpu_read(0xC000), 3);
+
+        // 32K mode ignores the low bank bit
+        write_serial(&mut mmc1, 0x8000, 0b0_0000);
+        assert_eq!(mmc1.cpu_read(0x8000), 2);
+        assert_eq!(mmc1.cpu_read(0xC000), 3);
+    }
+
+    #[test]
+    fn test_chr_modes() {
+        let mut mmc1 = mmc1(2, 8);
+
+        // 4K mode
+        write_serial(&mut mmc1, 0x8000, 0b1_1100);
+        write_serial(&mut mmc1, 0xA000, 5);
+        write_serial(&mut mmc1, 0xC000, 2);
+        assert_eq!(mmc1.ppu_read(0x0000), 5);
+        assert_eq!(mmc1.ppu_read(0x1000), 2);
+
+        // 8K mode
+        write_serial(&mut mmc1, 0x8000, 0b0_1100);
+        assert_eq!(mmc1.ppu_read(0x0000), 4);
+        assert_eq!(mmc1.ppu_read(0x1000), 5);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mmc1 = mmc1(2, 2);
+        write_serial(&mut mmc1, 0x8000, 0b0_1100);
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenLower);
+        write_serial(&mut mmc1, 0x8000, 0b0_1101);
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenUpper);
+        write_serial(&mut mmc1, 0x8000, 0b0_1110);
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+        write_serial(&mut mmc1, 0x8000, 0b0_1111);
+        assert_eq!(mmc1.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_reset_bit() {
+        let mut mmc1 = mmc1(8, 2);
+        write_serial(&mut mmc1, 0x8000, 0b0_0000);
+        mmc1.cpu_write(0x8000, 1);
+        mmc1.cpu_tick(2);
+        mmc1.cpu_write(0x8000, 0x80);
+        mmc1.cpu_tick(2);
+
+        assert_eq!(mmc1.shift_count, 0);
+        assert_eq!(mmc1.cpu_read(0xC000), 7);
+    }
+
+    #[test]
+    fn test_consecutive_writes_ignored() {
+        let mut cpu = CPU::new(Bus::new(mmc1_rom(16, 2)));
+        // INC $E000 writes back 15 (bit 1) and then 16, which is dropped.
+        // Five of them select bank 15
+        let mut program = [0xee, 0x00, 0xe0].repeat(5);
+        program.extend([0xad, 0x00, 0x80, 0x00]);
+        cpu.load(program);
+        // the banks hold no vectors, start the program directly
+        cpu.program_count = 0x0600;
+        cpu.halt_on_brk = true;
+        cpu.run();
+        assert_eq!(cpu.register_a, 15);
+    }
+
+    #[test]
+    fn test_prg_ram_disable() {
+        let mut mmc1 = mmc1(2, 2);
+        mmc1.cpu_write(0x6000, 0x55);
+        write_serial(&mut mmc1, 0xE000, 0x10);
+        assert_eq!(mmc1.cpu_read(0x6000), 0);
+        mmc1.cpu_write(0x6000, 0x66);
+        write_serial(&mut mmc1, 0xE000, 0x00);
+        assert_eq!(mmc1.cpu_read(0x6000), 0x55);
+    }
+}
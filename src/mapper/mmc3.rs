@@ -0,0 +1,347 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+/// A12 has to stay low for a few M2 cycles before a rise clocks the
+/// counter, which filters out the short dips between sprite fetches
+const A12_FILTER_CYCLES: usize = 3;
+
+/// Mapper 4: MMC3 (TxROM). 8K PRG and 1K/2K CHR banks, plus a scanline
+/// counter clocked by rising edges on PPU address line A12
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    four_screen: bool,
+    mirroring: Mirroring,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_cycles: usize,
+}
+
+impl Mmc3 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Mmc3 {
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            four_screen: rom.screen_mirroring == Mirroring::FourScreen,
+            mirroring: rom.screen_mirroring,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let last = banks - 1;
+        let slot = ((addr - 0x8000) as usize) / PRG_BANK_SIZE;
+        let prg_inverted = self.bank_select & 0x40 != 0;
+
+        let bank = match (slot, prg_inverted) {
+            (0, false) | (2, true) => self.registers[6] as usize,
+            // the second-last bank wraps around on an 8K image
+            (0, true) | (2, false) => (last + banks - 1) % banks,
+            (1, _) => self.registers[7] as usize,
+            _ => last,
+        };
+
+        (bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        // CHR inversion swaps the 2K and 1K halves of the pattern tables
+        let addr = if self.bank_select & 0x80 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+
+        let slot = addr as usize / CHR_BANK_SIZE;
+        let bank = match slot {
+            0 | 1 => (self.registers[0] as usize & !1) + slot,
+            2 | 3 => (self.registers[1] as usize & !1) + slot - 2,
+            _ => self.registers[slot - 2] as usize,
+        };
+
+        (bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    /// Tracks A12 on every pattern table access and clocks the IRQ counter
+    /// on a filtered rising edge
+    fn watch_a12(&mut self, addr: u16) {
+        let high = addr & 0x1000 != 0;
+        if high && !self.a12_high && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !high && self.a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = high;
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => super::read_prg_ram(&self.prg_ram, addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                super::write_prg_ram(&mut self.prg_ram, addr, data);
+            }
+            0x8000..=0x9FFF if even => self.bank_select = data,
+            0x8000..=0x9FFF => {
+                let register = (self.bank_select & 0b111) as usize;
+                self.registers[register] = data;
+            }
+            // four-screen boards ignore the mirroring register
+            0xA000..=0xBFFF if even && self.four_screen => {}
+            0xA000..=0xBFFF if even => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0xA000..=0xBFFF => {
+                self.prg_ram_enabled = data & 0x80 != 0;
+                self.prg_ram_write_protect = data & 0x40 != 0;
+            }
+            0xC000..=0xDFFF if even => self.irq_latch = data,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.watch_a12(addr);
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.watch_a12(addr);
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(cycles as usize);
+        }
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn mmc3(prg_banks: usize, chr_banks: usize) -> Mmc3 {
+        let mut rom = test_rom();
+        rom.prg_rom = (0..prg_banks)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..chr_banks)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        Mmc3::new(rom)
+    }
+
+    fn set_register(mmc3: &mut Mmc3, select: u8, value: u8) {
+        mmc3.cpu_write(0x8000, select);
+        mmc3.cpu_write(0x8001, value);
+    }
+
+    /// One scanline worth of fetches: background from $0000, sprites from $1000
+    fn scanline(mmc3: &mut Mmc3) {
+        mmc3.ppu_read(0x0000);
+        mmc3.cpu_tick(100);
+        mmc3.ppu_read(0x1000);
+        mmc3.cpu_tick(13);
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut mmc3 = mmc3(16, 8);
+        set_register(&mut mmc3, 6, 3);
+        set_register(&mut mmc3, 7, 5);
+        assert_eq!(mmc3.cpu_read(0x8000), 3);
+        assert_eq!(mmc3.cpu_read(0xA000), 5);
+        assert_eq!(mmc3.cpu_read(0xC000), 14);
+        assert_eq!(mmc3.cpu_read(0xE000), 15);
+
+        // PRG mode 1 swaps $8000 and $C000
+        set_register(&mut mmc3, 0x46, 3);
+        assert_eq!(mmc3.cpu_read(0x8000), 14);
+        assert_eq!(mmc3.cpu_read(0xA000), 5);
+        assert_eq!(mmc3.cpu_read(0xC000), 3);
+        assert_eq!(mmc3.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_prg_banking_8k() {
+        let mut mmc3 = mmc3(1, 8);
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000] {
+            assert_eq!(mmc3.cpu_read(addr), 0);
+        }
+        set_register(&mut mmc3, 0x46, 0);
+        assert_eq!(mmc3.cpu_read(0x8000), 0);
+
+        // NES 2.0 sizes can be smaller than a bank, which then repeats
+        let mut rom = test_rom();
+        rom.prg_rom = vec![0x42; 0x1000];
+        let mmc3 = Mmc3::new(rom);
+        assert_eq!(mmc3.cpu_peek(0xC000), 0x42);
+        assert_eq!(mmc3.cpu_peek(0xFFFF), 0x42);
+    }
+
+    #[test]
+    fn test_chr_banking() {
+        let mut mmc3 = mmc3(4, 16);
+        set_register(&mut mmc3, 0, 5);
+        set_register(&mut mmc3, 1, 8);
+        set_register(&mut mmc3, 2, 12);
+        set_register(&mut mmc3, 5, 15);
+        assert_eq!(mmc3.ppu_read(0x0000), 4);
+        assert_eq!(mmc3.ppu_read(0x0400), 5);
+        assert_eq!(mmc3.ppu_read(0x0800), 8);
+        assert_eq!(mmc3.ppu_read(0x0c00), 9);
+        assert_eq!(mmc3.ppu_read(0x1000), 12);
+        assert_eq!(mmc3.ppu_read(0x1c00), 15);
+
+        // CHR inversion puts the 1K banks at $0000
+        mmc3.cpu_write(0x8000, 0x80);
+        assert_eq!(mmc3.ppu_read(0x0000), 12);
+        assert_eq!(mmc3.ppu_read(0x1000), 4);
+        assert_eq!(mmc3.ppu_read(0x1800), 8);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut mmc3 = mmc3(4, 8);
+        mmc3.cpu_write(0xA000, 1);
+        assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+        mmc3.cpu_write(0xA000, 0);
+        assert_eq!(mmc3.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut mmc3 = mmc3(4, 8);
+        mmc3.cpu_write(0x6000, 0x55);
+        mmc3.cpu_write(0xA001, 0xc0);
+        mmc3.cpu_write(0x6000, 0x66);
+        assert_eq!(mmc3.cpu_read(0x6000), 0x55);
+
+        mmc3.cpu_write(0xA001, 0x00);
+        assert_eq!(mmc3.cpu_read(0x6000), 0);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mmc3 = mmc3(4, 8);
+        mmc3.cpu_write(0xC000, 2);
+        mmc3.cpu_write(0xC001, 0);
+        mmc3.cpu_write(0xE001, 0);
+
+        // reload to 2, then 1, then 0
+        scanline(&mut mmc3);
+        scanline(&mut mmc3);
+        assert!(!mmc3.irq());
+        scanline(&mut mmc3);
+        assert!(mmc3.irq());
+
+        mmc3.cpu_write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn test_a12_filter() {
+        let mut mmc3 = mmc3(4, 8);
+        mmc3.cpu_write(0xC000, 0);
+        mmc3.cpu_write(0xE001, 0);
+        mmc3.ppu_read(0x0000);
+        mmc3.cpu_tick(100);
+
+        // the first rise clocks, rises right after a short dip do not
+        mmc3.ppu_read(0x1000);
+        assert!(mmc3.irq());
+        mmc3.cpu_write(0xE000, 0);
+        mmc3.cpu_write(0xE001, 0);
+        mmc3.ppu_read(0x0000);
+        mmc3.ppu_read(0x1000);
+        assert!(!mmc3.irq());
+    }
+}
@@ -0,0 +1,919 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const EXRAM_SIZE: usize = 0x400;
+/// Nametable reads of the same address in a row that mark a new scanline
+const SCANLINE_MATCHES: u8 = 2;
+/// CPU cycles without a PPU read after which the frame is over
+const IDLE_CYCLES: u16 = 3;
+
+enum PrgTarget {
+    Rom(usize),
+    Ram(usize),
+}
+
+/// Mapper 5: MMC5 (ExROM). Fine grained PRG/CHR banking, 1K of ExRAM,
+/// per-nametable mapping with fill mode, a vertical split, a scanline IRQ
+/// and two pulse channels plus raw PCM
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    exram: Vec<u8>,
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// $5113-$5117
+    prg_banks: [u8; 5],
+    /// $5120-$5127, used for sprites and for everything with 8x8 sprites
+    chr_banks_a: [u16; 8],
+    /// $5128-$512B, used for the background with 8x16 sprites
+    chr_banks_b: [u16; 4],
+    chr_upper: u8,
+    last_chr_set_b: bool,
+    multiplicand: u8,
+    multiplier: u8,
+
+    // snooped from PPUCTRL, PPUMASK and the PPU fetches
+    large_sprites: bool,
+    rendering_enabled: bool,
+    fetching_sprites: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    /// Tile column of the current background fetch
+    tile_column: u8,
+    next_tile_column: u8,
+    /// The current background tile comes from the split
+    in_split: bool,
+    split_tile: u8,
+    /// ExRAM byte of the current tile in extended attribute mode
+    ext_attribute: u8,
+
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+    last_nametable_addr: u16,
+    nametable_matches: u8,
+    ppu_read_seen: bool,
+    idle_cycles: u16,
+
+    audio: Audio,
+}
+
+impl Mmc5 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Mmc5 {
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            exram: vec![0; EXRAM_SIZE],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0, 0],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0xff, 0xff, 0xff, 0xff],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            large_sprites: false,
+            rendering_enabled: false,
+            fetching_sprites: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            tile_column: 0,
+            next_tile_column: 0,
+            in_split: false,
+            split_tile: 0,
+            ext_attribute: 0,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            last_nametable_addr: 0,
+            nametable_matches: 0,
+            ppu_read_seen: false,
+            idle_cycles: 0,
+            audio: Audio::default(),
+        }
+    }
+
+    fn prg_target(&self, addr: u16) -> PrgTarget {
+        let (register, size) = match (self.prg_mode, addr) {
+            (_, 0x6000..=0x7FFF) => (0, 0x2000),
+            (0, _) => (4, 0x8000),
+            (1, 0x8000..=0xBFFF) | (2, 0x8000..=0xBFFF) => (2, 0x4000),
+            (1, _) => (4, 0x4000),
+            (2, 0xC000..=0xDFFF) => (3, 0x2000),
+            (2, _) => (4, 0x2000),
+            _ => (1 + (addr as usize - 0x8000) / PRG_BANK_SIZE, 0x2000),
+        };
+
+        let value = self.prg_banks[register];
+        let window = size / PRG_BANK_SIZE;
+        let inside = addr as usize & (size - 1);
+
+        // $5117 always maps ROM, $5114-$5116 pick ROM or RAM with bit 7
+        if register == 4 || (register > 0 && value & 0x80 != 0) {
+            let bank = (value & 0x7f) as usize & !(window - 1);
+            PrgTarget::Rom((bank * PRG_BANK_SIZE + inside) % self.prg_rom.len())
+        } else {
+            let bank = (value & 0x07) as usize & !(window - 1);
+            PrgTarget::Ram(bank * PRG_BANK_SIZE + inside)
+        }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0b10, 0b01]
+    }
+
+    fn rendering(&self) -> bool {
+        self.in_frame && self.rendering_enabled
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        let set_b = if self.rendering() {
+            self.large_sprites && !self.fetching_sprites
+        } else {
+            self.last_chr_set_b
+        };
+
+        let (bank, size) = match (set_b, self.chr_mode) {
+            (false, 0) => (self.chr_banks_a[7], 0x2000),
+            (false, 1) => (self.chr_banks_a[3 + 4 * (addr >> 12)], 0x1000),
+            (false, 2) => (self.chr_banks_a[1 + 2 * (addr >> 11)], 0x0800),
+            (false, _) => (self.chr_banks_a[addr >> 10], 0x0400),
+            (true, 0) => (self.chr_banks_b[3], 0x2000),
+            (true, 1) => (self.chr_banks_b[3], 0x1000),
+            (true, 2) => (self.chr_banks_b[1 + 2 * ((addr >> 11) & 1)], 0x0800),
+            (true, _) => (self.chr_banks_b[(addr >> 10) & 3], 0x0400),
+        };
+
+        (bank as usize * size + (addr & (size - 1))) % self.chr.len()
+    }
+
+    /// Three reads of the same nametable address in a row happen only at
+    /// the end of a rendered scanline, which is what the MMC5 counts
+    fn detect_scanline(&mut self, addr: u16) -> bool {
+        if addr != self.last_nametable_addr {
+            self.last_nametable_addr = addr;
+            self.nametable_matches = 0;
+            return false;
+        }
+
+        self.nametable_matches += 1;
+        if self.nametable_matches != SCANLINE_MATCHES {
+            return false;
+        }
+
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_target {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+        }
+        true
+    }
+
+    fn split_covers(&self, column: u8) -> bool {
+        if self.split_control & 0x80 == 0 || self.exram_mode > 1 {
+            return false;
+        }
+        let edge = self.split_control & 0x1f;
+        if self.split_control & 0x40 == 0 {
+            column < edge
+        } else {
+            column >= edge
+        }
+    }
+
+    /// Row of the split playfield drawn on the current scanline
+    fn split_y(&self) -> usize {
+        (self.split_scroll as usize + self.scanline as usize) % 240
+    }
+
+    fn split_nametable_read(&mut self, offset: u16) -> u8 {
+        let column = (self.tile_column & 0x1f) as usize;
+        let coarse_y = self.split_y() / 8;
+
+        if offset < 0x3c0 {
+            self.split_tile = self.exram[coarse_y * 32 + column];
+            self.split_tile
+        } else {
+            let attribute = self.exram[0x3c0 + (coarse_y / 4) * 8 + column / 4];
+            let shift = ((coarse_y & 2) << 1) | (column & 2);
+            ((attribute >> shift) & 0b11) * 0x55
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let data = self.cpu_peek(addr);
+        match addr {
+            0x5010 => self.audio.pcm_irq = false,
+            0x5204 => self.irq_pending = false,
+            0x8000..=0xBFFF => self.audio.pcm_read(data),
+            // the NMI vector fetch ends the frame
+            0xFFFA | 0xFFFB => self.in_frame = false,
+            _ => {}
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x5010 | 0x5015 => self.audio.read(addr),
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[(addr - 0x5c00) as usize],
+            0x6000..=0xFFFF => match self.prg_target(addr) {
+                PrgTarget::Rom(offset) => self.prg_rom[offset],
+                PrgTarget::Ram(_) if self.prg_ram.is_empty() => 0,
+                PrgTarget::Ram(offset) => self.prg_ram[offset % self.prg_ram.len()],
+            },
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5015 => self.audio.write(addr, data),
+            0x5100 => self.prg_mode = data & 0b11,
+            0x5101 => self.chr_mode = data & 0b11,
+            0x5102 | 0x5103 => self.prg_ram_protect[(addr - 0x5102) as usize] = data & 0b11,
+            0x5104 => self.exram_mode = data & 0b11,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = data,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[(addr - 0x5120) as usize] =
+                    data as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[(addr - 0x5128) as usize] =
+                    data as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = true;
+            }
+            0x5130 => self.chr_upper = data & 0b11,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_target = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5C00..=0x5FFF => {
+                let offset = (addr - 0x5c00) as usize;
+                match self.exram_mode {
+                    // the PPU owns ExRAM while it is a nametable, writes
+                    // outside of rendering store zero
+                    0 | 1 => self.exram[offset] = if self.in_frame { data } else { 0 },
+                    2 => self.exram[offset] = data,
+                    _ => {}
+                }
+            }
+            0x6000..=0xFFFF if self.prg_ram_writable() && !self.prg_ram.is_empty() => {
+                if let PrgTarget::Ram(offset) = self.prg_target(addr) {
+                    let len = self.prg_ram.len();
+                    self.prg_ram[offset % len] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.ppu_read_seen = true;
+        self.nametable_matches = 0;
+
+        if self.rendering() && !self.fetching_sprites {
+            if self.in_split {
+                let offset = self.split_bank as usize * 0x1000
+                    + self.split_tile as usize * 16
+                    + (addr as usize & 8)
+                    + (self.split_y() & 7);
+                return self.chr[offset % self.chr.len()];
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.ext_attribute & 0x3f) as usize | (self.chr_upper as usize) << 6;
+                let offset = bank * 0x1000 + (addr as usize & 0x0fff);
+                return self.chr[offset % self.chr.len()];
+            }
+        }
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    /// Only the CIRAM tables of $5105 can be expressed here, ExRAM and fill
+    /// mode tables are answered by `nametable_read`
+    fn mirroring(&self) -> Mirroring {
+        let mut pages = [None; 4];
+        for (table, page) in pages.iter_mut().enumerate() {
+            let source = (self.nametable_mapping >> (table * 2)) & 0b11;
+            if source <= 1 {
+                *page = Some(source);
+            }
+        }
+        super::closest_mirroring(pages)
+    }
+
+    fn nametable_read(&mut self, addr: u16) -> Option<u8> {
+        self.ppu_read_seen = true;
+        let offset = addr & 0x3ff;
+        let attribute = offset >= 0x3c0;
+
+        if self.detect_scanline(addr) {
+            // the third read is the first fetch for tile 2 of the new line,
+            // tiles 0 and 1 were prefetched at the end of the last one
+            self.next_tile_column = 2;
+        }
+
+        if self.rendering() && !self.fetching_sprites {
+            if !attribute {
+                self.tile_column = self.next_tile_column;
+                self.next_tile_column = self.next_tile_column.wrapping_add(1);
+                self.in_split = self.split_covers(self.tile_column);
+            }
+            if self.in_split {
+                return Some(self.split_nametable_read(offset));
+            }
+            if self.exram_mode == 1 {
+                if attribute {
+                    return Some((self.ext_attribute >> 6) * 0x55);
+                }
+                self.ext_attribute = self.exram[offset as usize];
+            }
+        }
+
+        let table = (addr >> 10) & 0b11;
+        match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            0 | 1 => None,
+            2 if self.exram_mode <= 1 => Some(self.exram[offset as usize]),
+            2 => Some(0),
+            _ if attribute => Some(self.fill_attribute * 0x55),
+            _ => Some(self.fill_tile),
+        }
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        let table = (addr >> 10) & 0b11;
+        match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            0 | 1 => false,
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[(addr & 0x3ff) as usize] = data;
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x2000 => self.large_sprites = data & 0x20 != 0,
+            0x2001 => {
+                self.rendering_enabled = data & 0x18 != 0;
+                if !self.rendering_enabled {
+                    self.in_frame = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn sprite_fetch(&mut self, active: bool) {
+        self.fetching_sprites = active;
+        if !active {
+            // the two background tiles fetched next start the next line
+            self.next_tile_column = 0;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_enabled && self.irq_pending) || self.audio.irq()
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        if self.ppu_read_seen {
+            self.idle_cycles = 0;
+        } else {
+            self.idle_cycles = self.idle_cycles.saturating_add(cycles as u16);
+            if self.idle_cycles >= IDLE_CYCLES {
+                self.in_frame = false;
+            }
+        }
+        self.ppu_read_seen = false;
+
+        self.audio.tick(cycles);
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// The MMC5 clocks envelopes and length counters at a fixed 240Hz
+const FRAME_PERIOD: u16 = 7457;
+
+/// Pulse channels like the APU's, without the sweep unit
+#[derive(Default)]
+struct Pulse {
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    length: u8,
+    /// Also loops the envelope
+    length_halt: bool,
+    constant_volume: bool,
+    /// Constant volume, or the envelope period
+    volume: u8,
+    envelope_start: bool,
+    envelope_divider: u8,
+    envelope_decay: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length_halt = data & 0x20 != 0;
+                self.constant_volume = data & 0x10 != 0;
+                self.volume = data & 0x0f;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((data as u16 & 0b111) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.envelope_start = true;
+                self.step = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    /// One APU cycle, every other CPU cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        if self.envelope_start {
+            self.envelope_start = false;
+            self.envelope_decay = 15;
+            self.envelope_divider = self.volume;
+        } else if self.envelope_divider == 0 {
+            self.envelope_divider = self.volume;
+            if self.envelope_decay > 0 {
+                self.envelope_decay -= 1;
+            } else if self.length_halt {
+                self.envelope_decay = 15;
+            }
+        } else {
+            self.envelope_divider -= 1;
+        }
+
+        if !self.length_halt && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else if self.constant_volume {
+            self.volume
+        } else {
+            self.envelope_decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct Audio {
+    pulse: [Pulse; 2],
+    /// PCM takes the CPU's reads from $8000-$BFFF instead of $5011 writes
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    pcm: u8,
+    odd_cycle: bool,
+    frame_counter: u16,
+}
+
+impl Audio {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x5010 => (self.pcm_irq as u8) << 7 | self.pcm_read_mode as u8,
+            _ => (self.pulse[0].length > 0) as u8 | ((self.pulse[1].length > 0) as u8) << 1,
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulse[0].write(addr - 0x5000, data),
+            0x5004..=0x5007 => self.pulse[1].write(addr - 0x5004, data),
+            0x5010 => {
+                self.pcm_read_mode = data & 0x01 != 0;
+                self.pcm_irq_enabled = data & 0x80 != 0;
+            }
+            // zero can't be written, it is the read mode IRQ marker
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+            0x5015 => {
+                self.pulse[0].set_enabled(data & 0x01 != 0);
+                self.pulse[1].set_enabled(data & 0x02 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    fn pcm_read(&mut self, data: u8) {
+        if !self.pcm_read_mode {
+            return;
+        }
+        if data == 0 {
+            self.pcm_irq = true;
+        } else {
+            self.pcm = data;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.pcm_irq_enabled && self.pcm_irq
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.odd_cycle = !self.odd_cycle;
+            if self.odd_cycle {
+                self.pulse[0].clock_timer();
+                self.pulse[1].clock_timer();
+            }
+
+            self.frame_counter += 1;
+            if self.frame_counter == FRAME_PERIOD {
+                self.frame_counter = 0;
+                self.pulse[0].clock_frame();
+                self.pulse[1].clock_frame();
+            }
+        }
+    }
+
+    /// Pulses and PCM get an even share of the output
+    fn output(&self) -> f32 {
+        let pulses = (self.pulse[0].output() + self.pulse[1].output()) as f32 / 30.0;
+        let pcm = self.pcm as f32 / 255.0;
+        (pulses + pcm) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn mmc5() -> Mmc5 {
+        let mut rom = test_rom();
+        rom.mapper = 5;
+        rom.prg_rom = (0..32)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..256).flat_map(|bank| vec![bank as u8; 0x400]).collect();
+        rom.prg_ram_size = 0x10000;
+        Mmc5::new(rom)
+    }
+
+    /// Rendering switched on, and one scanline already seen
+    fn rendering_mmc5() -> Mmc5 {
+        let mut mmc5 = mmc5();
+        mmc5.ppu_register_write(0x2001, 0x18);
+        end_of_scanline(&mut mmc5);
+        mmc5
+    }
+
+    /// Sprite fetches, the prefetch of tile 1, then the dummy nametable
+    /// fetches at dots 337 and 339 and the dot 1 fetch of tile 2
+    fn end_of_scanline(mmc5: &mut Mmc5) {
+        mmc5.sprite_fetch(true);
+        mmc5.sprite_fetch(false);
+        mmc5.nametable_read(0x2001);
+        mmc5.nametable_read(0x23C0);
+        for _ in 0..3 {
+            mmc5.nametable_read(0x2002);
+        }
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let mut mmc5 = mmc5();
+        // power on: mode 3 with $5117 = $FF
+        assert_eq!(mmc5.cpu_read(0xE000), 31);
+
+        mmc5.cpu_write(0x5100, 0);
+        mmc5.cpu_write(0x5117, 0x87);
+        assert_eq!(mmc5.cpu_read(0x8000), 4);
+        assert_eq!(mmc5.cpu_read(0xE000), 7);
+
+        mmc5.cpu_write(0x5100, 2);
+        mmc5.cpu_write(0x5115, 0x8b);
+        mmc5.cpu_write(0x5116, 0x89);
+        assert_eq!(mmc5.cpu_read(0x8000), 10);
+        assert_eq!(mmc5.cpu_read(0xA000), 11);
+        assert_eq!(mmc5.cpu_read(0xC000), 9);
+        assert_eq!(mmc5.cpu_read(0xE000), 7);
+
+        mmc5.cpu_write(0x5100, 3);
+        mmc5.cpu_write(0x5114, 0x85);
+        assert_eq!(mmc5.cpu_read(0x8000), 5);
+        assert_eq!(mmc5.cpu_read(0xA000), 11);
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x6000, 0x55);
+        assert_eq!(mmc5.cpu_read(0x6000), 0);
+
+        mmc5.cpu_write(0x5102, 0b10);
+        mmc5.cpu_write(0x5103, 0b01);
+        mmc5.cpu_write(0x5113, 2);
+        mmc5.cpu_write(0x6000, 0x55);
+        // RAM bank 2 mapped into $8000 as well
+        mmc5.cpu_write(0x5114, 0x02);
+        assert_eq!(mmc5.cpu_read(0x6000), 0x55);
+        assert_eq!(mmc5.cpu_read(0x8000), 0x55);
+    }
+
+    #[test]
+    fn test_chr_sets() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5101, 3);
+        mmc5.cpu_write(0x5130, 0);
+        mmc5.cpu_write(0x5124, 40);
+        mmc5.cpu_write(0x5128, 50);
+        // outside of rendering the last written set is used
+        assert_eq!(mmc5.ppu_read(0x1000), 50);
+        mmc5.cpu_write(0x5121, 41);
+        assert_eq!(mmc5.ppu_read(0x1000), 40);
+        assert_eq!(mmc5.ppu_read(0x0400), 41);
+
+        // 8x16 sprites: set A for sprites, set B for the background
+        mmc5.ppu_register_write(0x2000, 0x20);
+        mmc5.ppu_register_write(0x2001, 0x18);
+        end_of_scanline(&mut mmc5);
+        assert_eq!(mmc5.ppu_read(0x1000), 50);
+        mmc5.sprite_fetch(true);
+        assert_eq!(mmc5.ppu_read(0x1000), 40);
+    }
+
+    #[test]
+    fn test_chr_upper_bits() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5101, 3);
+        mmc5.cpu_write(0x5130, 1);
+        mmc5.cpu_write(0x5120, 0x80);
+        // bank $180 wraps in 256K of CHR
+        assert_eq!(mmc5.ppu_read(0x0000), 0x80);
+        assert_eq!(mmc5.chr_banks_a[0], 0x180);
+    }
+
+    #[test]
+    fn test_nametable_mapping() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5104, 2);
+        mmc5.cpu_write(0x5C05, 0x42);
+        mmc5.cpu_write(0x5104, 0);
+        mmc5.cpu_write(0x5106, 0x77);
+        mmc5.cpu_write(0x5107, 0x02);
+        // CIRAM A, CIRAM B, ExRAM, fill
+        mmc5.cpu_write(0x5105, 0b1110_0100);
+
+        assert_eq!(mmc5.nametable_read(0x2005), None);
+        assert_eq!(mmc5.nametable_read(0x2405), None);
+        assert_eq!(mmc5.nametable_read(0x2805), Some(0x42));
+        assert_eq!(mmc5.nametable_read(0x2C05), Some(0x77));
+        assert_eq!(mmc5.nametable_read(0x2FC0), Some(0xaa));
+        assert_eq!(mmc5.mirroring(), Mirroring::Vertical);
+
+        assert!(mmc5.nametable_write(0x2806, 0x11));
+        assert!(!mmc5.nametable_write(0x2006, 0x11));
+        assert_eq!(mmc5.nametable_read(0x2806), Some(0x11));
+    }
+
+    #[test]
+    fn test_exram_cpu_access() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5104, 2);
+        mmc5.cpu_write(0x5C00, 0x12);
+        assert_eq!(mmc5.cpu_read(0x5C00), 0x12);
+
+        mmc5.cpu_write(0x5104, 3);
+        mmc5.cpu_write(0x5C00, 0x34);
+        assert_eq!(mmc5.cpu_read(0x5C00), 0x12);
+
+        // as a nametable, writes outside of rendering store zero
+        mmc5.cpu_write(0x5104, 0);
+        mmc5.cpu_write(0x5C00, 0x34);
+        assert_eq!(mmc5.cpu_read(0x5C00), 0);
+        mmc5.cpu_write(0x5104, 2);
+        assert_eq!(mmc5.cpu_read(0x5C00), 0);
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5104, 2);
+        mmc5.cpu_write(0x5C03, 0b1100_0101);
+        mmc5.cpu_write(0x5104, 1);
+        mmc5.ppu_register_write(0x2001, 0x18);
+        end_of_scanline(&mut mmc5);
+
+        assert_eq!(mmc5.nametable_read(0x2003), None);
+        assert_eq!(mmc5.nametable_read(0x23C0), Some(0xff));
+        // 4K bank 5 is 1K bank 20
+        assert_eq!(mmc5.ppu_read(0x0010), 20);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mmc5 = rendering_mmc5();
+        mmc5.cpu_write(0x5203, 2);
+        mmc5.cpu_write(0x5204, 0x80);
+        assert_eq!(mmc5.cpu_read(0x5204), 0x40);
+
+        end_of_scanline(&mut mmc5);
+        assert!(!mmc5.irq());
+        end_of_scanline(&mut mmc5);
+        assert!(mmc5.irq());
+
+        // peeking leaves the IRQ alone, reading acknowledges it
+        assert_eq!(mmc5.cpu_peek(0x5204), 0xc0);
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.cpu_read(0x5204), 0xc0);
+        assert!(!mmc5.irq());
+    }
+
+    #[test]
+    fn test_in_frame_ends_without_ppu_reads() {
+        let mut mmc5 = rendering_mmc5();
+        mmc5.cpu_tick(2);
+        assert_eq!(mmc5.cpu_read(0x5204) & 0x40, 0x40);
+        mmc5.cpu_tick(3);
+        assert_eq!(mmc5.cpu_read(0x5204) & 0x40, 0);
+    }
+
+    #[test]
+    fn test_nmi_vector_read_ends_frame() {
+        let mut mmc5 = rendering_mmc5();
+        mmc5.cpu_peek(0xFFFA);
+        assert_eq!(mmc5.cpu_peek(0x5204) & 0x40, 0x40);
+        mmc5.cpu_read(0xFFFA);
+        assert_eq!(mmc5.cpu_read(0x5204) & 0x40, 0);
+    }
+
+    #[test]
+    fn test_split_screen() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5104, 2);
+        // row 1 of the split, column 2
+        mmc5.cpu_write(0x5C22, 0x03);
+        mmc5.cpu_write(0x5104, 0);
+        mmc5.cpu_write(0x5200, 0x80 | 4);
+        mmc5.cpu_write(0x5201, 8);
+        mmc5.cpu_write(0x5202, 1);
+        mmc5.ppu_register_write(0x2001, 0x18);
+        mmc5.cpu_write(0x5105, 0x00);
+        // the last read fetches tile column 2, left of the split edge
+        end_of_scanline(&mut mmc5);
+        assert_eq!(mmc5.split_tile, 0x03);
+        // 4K bank 1, tile 3, fine y 0
+        assert_eq!(mmc5.ppu_read(0x0000), 4);
+        // column 3 is still split, 4 is the first column of the playfield
+        assert_eq!(mmc5.nametable_read(0x2003), Some(0));
+        assert_eq!(mmc5.nametable_read(0x2004), None);
+    }
+
+    #[test]
+    fn test_multiplier() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5205, 200);
+        mmc5.cpu_write(0x5206, 100);
+        assert_eq!(mmc5.cpu_read(0x5205), (20000 & 0xff) as u8);
+        assert_eq!(mmc5.cpu_read(0x5206), (20000 >> 8) as u8);
+    }
+
+    #[test]
+    fn test_pulse() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5015, 0x01);
+        // 50% duty, constant volume 15, shortest period
+        mmc5.cpu_write(0x5000, 0b1011_1111);
+        mmc5.cpu_write(0x5002, 0);
+        mmc5.cpu_write(0x5003, 0x08);
+        assert_eq!(mmc5.cpu_read(0x5015), 0x01);
+
+        let mut levels = vec![];
+        for _ in 0..8 {
+            mmc5.cpu_tick(2);
+            levels.push(mmc5.audio_output());
+        }
+        assert_eq!(levels.iter().filter(|&&level| level > 0.0).count(), 4);
+
+        mmc5.cpu_write(0x5015, 0x00);
+        assert_eq!(mmc5.cpu_read(0x5015), 0x00);
+        assert_eq!(mmc5.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_pcm() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5011, 0xff);
+        assert_eq!(mmc5.audio_output(), 0.5);
+
+        // read mode: a zero byte raises the IRQ
+        mmc5.cpu_write(0x5010, 0x81);
+        mmc5.cpu_write(0x5100, 3);
+        mmc5.cpu_write(0x5114, 0x80);
+        mmc5.cpu_peek(0x8000);
+        assert!(!mmc5.irq());
+        mmc5.cpu_read(0x8000);
+        assert!(mmc5.irq());
+        assert_eq!(mmc5.cpu_peek(0x5010), 0x81);
+        assert_eq!(mmc5.cpu_read(0x5010), 0x81);
+        assert!(!mmc5.irq());
+    }
+}
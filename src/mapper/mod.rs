@@ -0,0 +1,333 @@
+pub mod axrom;
+pub mod cnrom;
+pub mod color_dreams;
+pub mod fme7;
+mod four_screen;
+pub mod gxrom;
+pub mod mmc1;
+pub mod mmc3;
+pub mod mmc5;
+pub mod namco163;
+pub mod nrom;
+pub mod uxrom;
+pub mod vrc;
+pub mod vrc6;
+mod vrc_irq;
+
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_RAM_START: u16 = 0x6000;
+const TRAINER_START: u16 = 0x7000;
+const PRG_RAM_PAGE_SIZE: usize = 0x2000;
+const CHR_RAM_PAGE_SIZE: usize = 0x2000;
+
+/// Mapper numbers `new` can build a board for
+const SUPPORTED_MAPPERS: [u16; 17] = [0, 1, 2, 3, 4, 5, 7, 11, 19, 21, 22, 23, 24, 25, 26, 66, 69];
+
+/// A cartridge board: everything the CPU sees from $4020 to $FFFF and the
+/// PPU sees from $0000 to $1FFF goes through it
+pub trait Mapper {
+    /// CPU reads in $4020-$FFFF. Boards with registers that change when
+    /// read override this, the rest only implement `cpu_peek`
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    /// CPU reads without their side effects, for traces and debuggers
+    fn cpu_peek(&self, addr: u16) -> u8;
+
+    /// CPU writes in $4020-$FFFF
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// PPU pattern table reads in $0000-$1FFF
+    fn ppu_read(&mut self, addr: u16) -> u8;
+
+    /// PPU pattern table writes in $0000-$1FFF, only CHR RAM takes them
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    /// Current nametable arrangement, fixed by solder pads on simple boards
+    fn mirroring(&self) -> Mirroring;
+
+    /// PPU nametable reads in $2000-$2FFF. Boards with their own nametable
+    /// memory answer with `Some`, `None` leaves the read to the console VRAM
+    fn nametable_read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// PPU nametable writes, returns whether the board took the write
+    fn nametable_write(&mut self, _addr: u16, _data: u8) -> bool {
+        false
+    }
+
+    /// CPU writes to the PPU registers, which some boards snoop
+    fn ppu_register_write(&mut self, _addr: u16, _data: u8) {}
+
+    /// Called by the PPU when it starts (dot 257) and stops (dot 321)
+    /// fetching sprite patterns
+    fn sprite_fetch(&mut self, _active: bool) {}
+
+    /// Level of the cartridge's IRQ output
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Called after every CPU instruction with the cycles it took
+    fn cpu_tick(&mut self, _cycles: u8) {}
+
+    /// Called by the PPU at the end of every rendered scanline
+    fn scanline_tick(&mut self) {}
+
+    /// Level of the cartridge's expansion audio, between 0.0 and 1.0
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// PRG RAM, if the board has any
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        None
+    }
+}
+
+pub fn is_supported(mapper: u16) -> bool {
+    SUPPORTED_MAPPERS.contains(&mapper)
+}
+
+/// Builds the board for `rom.mapper`, `Rom::new` only accepts supported mappers
+pub fn new(rom: Rom) -> Box<dyn Mapper> {
+    let four_screen = rom.screen_mirroring == Mirroring::FourScreen;
+    let board: Box<dyn Mapper> = match rom.mapper {
+        0 => Box::new(nrom::Nrom::new(rom)),
+        1 => Box::new(mmc1::Mmc1::new(rom)),
+        2 => Box::new(uxrom::Uxrom::new(rom)),
+        3 => Box::new(cnrom::Cnrom::new(rom)),
+        4 => Box::new(mmc3::Mmc3::new(rom)),
+        5 => Box::new(mmc5::Mmc5::new(rom)),
+        7 => Box::new(axrom::Axrom::new(rom)),
+        11 => Box::new(color_dreams::ColorDreams::new(rom)),
+        19 => Box::new(namco163::Namco163::new(rom)),
+        21 | 22 | 23 | 25 => Box::new(vrc::Vrc::new(rom)),
+        24 | 26 => Box::new(vrc6::Vrc6::new(rom)),
+        66 => Box::new(gxrom::Gxrom::new(rom)),
+        69 => Box::new(fme7::Fme7::new(rom)),
+        mapper => panic!("mapper {} is not supported", mapper),
+    };
+
+    if four_screen {
+        Box::new(four_screen::FourScreen::new(board))
+    } else {
+        board
+    }
+}
+
+/// PRG RAM sized from the header, with the trainer already in place at $7000
+fn prg_ram(rom: &Rom) -> Vec<u8> {
+    let mut size = rom.prg_ram_size + rom.prg_nvram_size;
+    if rom.trainer.is_some() {
+        size = size.max(PRG_RAM_PAGE_SIZE);
+    }
+
+    let mut prg_ram = vec![0; size];
+    if let Some(trainer) = &rom.trainer {
+        let start = (TRAINER_START - PRG_RAM_START) as usize;
+        prg_ram[start..start + trainer.len()].copy_from_slice(trainer);
+    }
+    prg_ram
+}
+
+/// CHR ROM, or CHR RAM when the cartridge carries none. The flag tells
+/// whether the memory is writable
+fn chr_memory(rom: &Rom) -> (Vec<u8>, bool) {
+    if rom.chr_rom.is_empty() {
+        let size = (rom.chr_ram_size + rom.chr_nvram_size).max(CHR_RAM_PAGE_SIZE);
+        (vec![0; size], true)
+    } else {
+        (rom.chr_rom.clone(), false)
+    }
+}
+
+/// PRG RAM and CHR memory of the discrete logic boards, which only differ
+/// in how they bank PRG ROM and CHR
+struct DiscreteMemory {
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+}
+
+impl DiscreteMemory {
+    /// These boards carry no PRG RAM of their own, so it is only mapped when
+    /// the header asks for it: a NES 2.0 size, a battery or a trainer. The
+    /// 8K iNES 1.0 assumes for every cartridge doesn't count
+    fn new(rom: &Rom) -> Self {
+        let prg_ram = if rom.nes2 || rom.battery || rom.trainer.is_some() {
+            prg_ram(rom)
+        } else {
+            Vec::new()
+        };
+        let (chr, chr_is_ram) = chr_memory(rom);
+
+        DiscreteMemory {
+            prg_ram,
+            chr,
+            chr_is_ram,
+        }
+    }
+
+    fn read_prg_ram(&self, addr: u16) -> u8 {
+        read_prg_ram(&self.prg_ram, addr)
+    }
+
+    fn write_prg_ram(&mut self, addr: u16, data: u8) {
+        write_prg_ram(&mut self.prg_ram, addr, data);
+    }
+
+    /// `offset` is the banked CHR address, mirrored over the CHR size
+    fn read_chr(&self, offset: usize) -> u8 {
+        self.chr[offset % self.chr.len()]
+    }
+
+    /// Only CHR RAM takes writes
+    fn write_chr(&mut self, offset: usize, data: u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[offset % len] = data;
+        }
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+/// Boards that map each nametable to a console VRAM page on their own get
+/// the standard arrangement that agrees with every page they pick. `None`
+/// marks tables the board serves itself through `nametable_read`
+fn closest_mirroring(pages: [Option<u8>; 4]) -> Mirroring {
+    let fits = |layout: [u8; 4]| {
+        pages
+            .iter()
+            .zip(layout.iter())
+            .all(|(page, expected)| page.is_none_or(|page| page == *expected))
+    };
+
+    if fits([0, 1, 0, 1]) {
+        Mirroring::Vertical
+    } else if fits([0, 0, 1, 1]) {
+        Mirroring::Horizontal
+    } else if fits([0, 0, 0, 0]) {
+        Mirroring::SingleScreenLower
+    } else {
+        Mirroring::SingleScreenUpper
+    }
+}
+
+/// NES 2.0 submappers 1 and 2 of the discrete boards say whether the ROM
+/// drives the data bus during register writes, older dumps get the default
+fn has_bus_conflicts(rom: &Rom, default: bool) -> bool {
+    match rom.submapper {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
+}
+
+/// With a bus conflict the ROM and the CPU drive the bus at once, and the
+/// register only latches the bits that are low in neither
+fn bus_conflict(prg_rom: &[u8], offset: usize, data: u8) -> u8 {
+    data & prg_rom[offset % prg_rom.len()]
+}
+
+/// Without PRG RAM the read is 0 on purpose, not open bus: boards never see
+/// what was last on the data bus, only `Bus` does for $4000-$401F
+fn read_prg_ram(prg_ram: &[u8], addr: u16) -> u8 {
+    if prg_ram.is_empty() {
+        return 0;
+    }
+    prg_ram[(addr - PRG_RAM_START) as usize % prg_ram.len()]
+}
+
+fn write_prg_ram(prg_ram: &mut [u8], addr: u16, data: u8) {
+    if prg_ram.is_empty() {
+        return;
+    }
+    let len = prg_ram.len();
+    prg_ram[(addr - PRG_RAM_START) as usize % len] = data;
+}
+
+fn save_ram(prg_ram: &[u8]) -> Option<&[u8]> {
+    if prg_ram.is_empty() {
+        None
+    } else {
+        Some(prg_ram)
+    }
+}
+
+fn save_ram_mut(prg_ram: &mut [u8]) -> Option<&mut [u8]> {
+    if prg_ram.is_empty() {
+        None
+    } else {
+        Some(prg_ram)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    /// `count` banks of `size` bytes, each filled with its bank number OR'd
+    /// into `fill`
+    pub fn banks(count: usize, size: usize, fill: u8) -> Vec<u8> {
+        (0..count)
+            .flat_map(|bank| vec![fill | bank as u8; size])
+            .collect()
+    }
+
+    /// The test cartridge with other ROM contents, an empty CHR ROM gets
+    /// CHR RAM
+    pub fn cartridge(submapper: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Rom {
+        let mut rom = test_rom();
+        rom.submapper = submapper;
+        rom.prg_rom = prg_rom;
+        rom.chr_rom = chr_rom;
+        rom
+    }
+
+    #[test]
+    fn test_discrete_prg_ram_needs_declaring() {
+        let mut rom = test_rom();
+        assert!(DiscreteMemory::new(&rom).save_ram().is_none());
+
+        rom.battery = true;
+        let mut memory = DiscreteMemory::new(&rom);
+        memory.write_prg_ram(0x6123, 0x55);
+        assert_eq!(memory.read_prg_ram(0x6123), 0x55);
+        assert_eq!(memory.save_ram().unwrap().len(), PRG_RAM_PAGE_SIZE);
+
+        rom.battery = false;
+        rom.nes2 = true;
+        rom.prg_ram_size = 0;
+        assert!(DiscreteMemory::new(&rom).save_ram().is_none());
+    }
+
+    #[test]
+    fn test_discrete_chr_ram() {
+        let mut rom = cartridge(0, test_rom().prg_rom, vec![1; CHR_RAM_PAGE_SIZE]);
+        let mut memory = DiscreteMemory::new(&rom);
+        memory.write_chr(0x0123, 0x55);
+        assert_eq!(memory.read_chr(0x0123), 1);
+
+        rom.chr_rom = vec![];
+        let mut memory = DiscreteMemory::new(&rom);
+        memory.write_chr(0x2123, 0x55);
+        assert_eq!(memory.read_chr(0x0123), 0x55);
+    }
+}
@@ -0,0 +1,407 @@
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const SOUND_RAM_SIZE: usize = 0x80;
+/// Channel registers live in the top of the sound RAM, 8 bytes each
+const CHANNEL_REGISTERS: usize = 0x40;
+/// The chip updates one channel every 15 CPU cycles
+const CYCLES_PER_CHANNEL: u8 = 15;
+/// Bank numbers from $E0 up select a console VRAM page
+const CIRAM_BANKS: u8 = 0xe0;
+const IRQ_COUNTER_MAX: u16 = 0x7fff;
+/// Loudest sample (15) at full volume (15)
+const MAX_CHANNEL_OUTPUT: f32 = 225.0;
+
+/// Mapper 19: Namco 163. 8K PRG and 1K CHR banks, CHR ROM usable as
+/// nametables, a CPU cycle IRQ counter and up to 8 wavetable channels that
+/// play from 128 bytes of internal RAM
+///
+/// Pattern tables can't be pointed at console VRAM, those banks read CHR
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    /// $F800: write enable in the high nibble, protected 2K blocks below
+    prg_ram_protect: u8,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    sound_ram: Vec<u8>,
+    /// $F800 address port, the data port is $4800
+    sound_address: u8,
+    sound_auto_increment: bool,
+    sound_disabled: bool,
+    channel_outputs: [u8; 8],
+    current_channel: usize,
+    channel_cycles: u8,
+}
+
+impl Namco163 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Namco163 {
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0; 8],
+            nametable_banks: [CIRAM_BANKS, CIRAM_BANKS + 1, CIRAM_BANKS, CIRAM_BANKS + 1],
+            prg_ram_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            sound_ram: vec![0; SOUND_RAM_SIZE],
+            sound_address: 0,
+            sound_auto_increment: false,
+            sound_disabled: false,
+            channel_outputs: [0; 8],
+            current_channel: 7,
+            channel_cycles: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF => self.prg_banks[2] as usize,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).max(1) - 1,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, bank: u8, addr: u16) -> usize {
+        (bank as usize * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let block = (addr - 0x6000) >> 11;
+        self.prg_ram_protect & 0xf0 == 0x40 && self.prg_ram_protect & (1 << block) == 0
+    }
+
+    fn active_channels(&self) -> usize {
+        ((self.sound_ram[0x7f] >> 4) & 0b111) as usize + 1
+    }
+
+    /// Both reads and writes of the data port step the address when asked to
+    fn sound_data_access(&mut self) {
+        if self.sound_auto_increment {
+            self.sound_address = (self.sound_address + 1) & 0x7f;
+        }
+    }
+
+    fn sound_data_write(&mut self, data: u8) {
+        self.sound_ram[self.sound_address as usize] = data;
+        self.sound_data_access();
+    }
+
+    /// Advances one channel's 24 bit phase by its 18 bit frequency and
+    /// looks up the 4 bit sample it now points at
+    fn update_channel(&mut self, channel: usize) {
+        let base = CHANNEL_REGISTERS + channel * 8;
+        let ram = &mut self.sound_ram;
+
+        let frequency =
+            ram[base] as u32 | (ram[base + 2] as u32) << 8 | (ram[base + 4] as u32 & 0b11) << 16;
+        let phase =
+            ram[base + 1] as u32 | (ram[base + 3] as u32) << 8 | (ram[base + 5] as u32) << 16;
+        let length = (256 - (ram[base + 4] & 0xfc) as u32) << 16;
+
+        let phase = (phase + frequency) % length;
+        ram[base + 1] = phase as u8;
+        ram[base + 3] = (phase >> 8) as u8;
+        ram[base + 5] = (phase >> 16) as u8;
+
+        let index = ((phase >> 16) + ram[base + 6] as u32) & 0xff;
+        let sample = (ram[index as usize / 2] >> ((index & 1) * 4)) & 0x0f;
+        self.channel_outputs[channel] = sample * (ram[base + 7] & 0x0f);
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let data = self.cpu_peek(addr);
+        if let 0x4800..=0x4FFF = addr {
+            self.sound_data_access();
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.sound_ram[self.sound_address as usize],
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+            0x6000..=0x7FFF => super::read_prg_ram(&self.prg_ram, addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.sound_data_write(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7f00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00ff) | (data as u16 & 0x7f) << 8;
+                self.irq_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                super::write_prg_ram(&mut self.prg_ram, addr, data);
+            }
+            0x8000..=0xBFFF => self.chr_banks[((addr - 0x8000) >> 11) as usize] = data,
+            0xC000..=0xDFFF => self.nametable_banks[((addr - 0xc000) >> 11) as usize] = data,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3f;
+                self.sound_disabled = data & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data & 0x3f,
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3f,
+            0xF800..=0xFFFF => {
+                self.prg_ram_protect = data;
+                self.sound_address = data & 0x7f;
+                self.sound_auto_increment = data & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+        self.chr[self.chr_offset(bank, addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE];
+            let offset = self.chr_offset(bank, addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        let mut pages = [None; 4];
+        for (page, &bank) in pages.iter_mut().zip(self.nametable_banks.iter()) {
+            if bank >= CIRAM_BANKS {
+                *page = Some(bank & 1);
+            }
+        }
+        super::closest_mirroring(pages)
+    }
+
+    fn nametable_read(&mut self, addr: u16) -> Option<u8> {
+        let bank = self.nametable_banks[((addr >> 10) & 0b11) as usize];
+        if bank >= CIRAM_BANKS {
+            None
+        } else {
+            Some(self.chr[self.chr_offset(bank, addr)])
+        }
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8) -> bool {
+        let bank = self.nametable_banks[((addr >> 10) & 0b11) as usize];
+        if bank >= CIRAM_BANKS {
+            return false;
+        }
+        if self.chr_is_ram {
+            let offset = self.chr_offset(bank, addr);
+            self.chr[offset] = data;
+        }
+        true
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter = (self.irq_counter + cycles as u16).min(IRQ_COUNTER_MAX);
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_pending = true;
+            }
+        }
+
+        if self.sound_disabled {
+            return;
+        }
+        self.channel_cycles += cycles;
+        while self.channel_cycles >= CYCLES_PER_CHANNEL {
+            self.channel_cycles -= CYCLES_PER_CHANNEL;
+
+            // channels are serviced from 7 down to 8 - active
+            let lowest = 8 - self.active_channels();
+            self.update_channel(self.current_channel);
+            self.current_channel = if self.current_channel <= lowest {
+                7
+            } else {
+                self.current_channel - 1
+            };
+        }
+    }
+
+    /// The chip plays one channel at a time, which averages out to the mean
+    /// of the active channels
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+        let active = self.active_channels();
+        let sum: u32 = self.channel_outputs[8 - active..]
+            .iter()
+            .map(|&output| output as u32)
+            .sum();
+        sum as f32 / (active as f32 * MAX_CHANNEL_OUTPUT)
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn namco163() -> Namco163 {
+        let mut rom = test_rom();
+        rom.mapper = 19;
+        rom.prg_rom = (0..16)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..64)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        Namco163::new(rom)
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut namco = namco163();
+        namco.cpu_write(0xE000, 3);
+        namco.cpu_write(0xE800, 4);
+        namco.cpu_write(0xF000, 5);
+        assert_eq!(namco.cpu_read(0x8000), 3);
+        assert_eq!(namco.cpu_read(0xA000), 4);
+        assert_eq!(namco.cpu_read(0xC000), 5);
+        assert_eq!(namco.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_prg_smaller_than_a_bank() {
+        let mut rom = test_rom();
+        rom.mapper = 19;
+        rom.prg_rom = vec![0x42; 0x1000];
+        let namco = Namco163::new(rom);
+        assert_eq!(namco.cpu_peek(0xE000), 0x42);
+        assert_eq!(namco.cpu_peek(0xFFFF), 0x42);
+    }
+
+    #[test]
+    fn test_chr_and_nametable_banks() {
+        let mut namco = namco163();
+        namco.cpu_write(0x8800, 9);
+        assert_eq!(namco.ppu_read(0x0400), 9);
+
+        namco.cpu_write(0xC000, 0xe0);
+        namco.cpu_write(0xC800, 0xe1);
+        namco.cpu_write(0xD000, 0x21);
+        namco.cpu_write(0xD800, 0xe1);
+        assert_eq!(namco.nametable_read(0x2000), None);
+        assert_eq!(namco.nametable_read(0x2800), Some(0x21));
+        assert!(namco.nametable_write(0x2800, 0));
+        assert!(!namco.nametable_write(0x2400, 0));
+        assert_eq!(namco.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_prg_ram_protect() {
+        let mut namco = namco163();
+        namco.cpu_write(0x6000, 0x55);
+        assert_eq!(namco.cpu_read(0x6000), 0);
+
+        // enabled, with the second 2K block protected
+        namco.cpu_write(0xF800, 0x42);
+        namco.cpu_write(0x6000, 0x55);
+        namco.cpu_write(0x6800, 0x66);
+        assert_eq!(namco.cpu_read(0x6000), 0x55);
+        assert_eq!(namco.cpu_read(0x6800), 0);
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut namco = namco163();
+        namco.cpu_write(0x5000, 0xfc);
+        namco.cpu_write(0x5800, 0xff);
+        namco.cpu_tick(2);
+        assert!(!namco.irq());
+        namco.cpu_tick(2);
+        assert!(namco.irq());
+        assert_eq!(namco.cpu_read(0x5000), 0xff);
+
+        namco.cpu_write(0x5800, 0x7f);
+        assert!(!namco.irq());
+    }
+
+    #[test]
+    fn test_sound_ram_ports() {
+        let mut namco = namco163();
+        namco.cpu_write(0xF800, 0x80 | 0x10);
+        namco.cpu_write(0x4800, 0x12);
+        namco.cpu_write(0x4800, 0x34);
+
+        namco.cpu_write(0xF800, 0x80 | 0x10);
+        assert_eq!(namco.cpu_peek(0x4800), 0x12);
+        assert_eq!(namco.cpu_read(0x4800), 0x12);
+        assert_eq!(namco.cpu_read(0x4800), 0x34);
+    }
+
+    #[test]
+    fn test_wave_channel() {
+        let mut namco = namco163();
+        // a 4 sample wave at address 0: 0, 15, 0, 0
+        namco.cpu_write(0xF800, 0x80);
+        namco.cpu_write(0x4800, 0xf0);
+
+        // channel 7 moves one sample per update over a 4 sample wave at
+        // volume 15, and is the only active channel
+        namco.cpu_write(0xF800, 0x80 | 0x78);
+        for data in [0x00, 0x00, 0x00, 0x00, 0xfc | 0x01, 0x00, 0x00, 0x0f] {
+            namco.cpu_write(0x4800, data);
+        }
+
+        namco.cpu_tick(15);
+        assert_eq!(namco.audio_output(), 1.0);
+        namco.cpu_tick(15);
+        assert_eq!(namco.audio_output(), 0.0);
+        namco.cpu_tick(15);
+        namco.cpu_tick(15);
+        namco.cpu_tick(15);
+        assert_eq!(namco.audio_output(), 1.0);
+
+        namco.cpu_write(0xE000, 0x40);
+        assert_eq!(namco.audio_output(), 0.0);
+    }
+}
@@ -0,0 +1,118 @@
+use super::DiscreteMemory;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+/// Mapper 0: no bank switching, 16K PRG ROM is mirrored into $C000-$FFFF
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    memory: DiscreteMemory,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        let memory = DiscreteMemory::new(&rom);
+
+        Nrom {
+            prg_rom: rom.prg_rom,
+            memory,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(addr),
+            0x8000..=0xFFFF => {
+                let addr = (addr - 0x8000) as usize;
+                self.prg_rom[addr % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.memory.write_prg_ram(addr, data);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.memory.read_chr(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.memory.write_chr(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.memory.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.memory.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    #[test]
+    fn test_16k_prg_is_mirrored() {
+        let mut rom = test_rom();
+        rom.prg_rom[0x0010] = 0x42;
+        let nrom = Nrom::new(rom);
+
+        assert_eq!(nrom.cpu_peek(0x8010), 0x42);
+        assert_eq!(nrom.cpu_peek(0xC010), 0x42);
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut rom = test_rom();
+        rom.battery = true;
+        let mut nrom = Nrom::new(rom);
+        nrom.cpu_write(0x6123, 0x55);
+
+        assert_eq!(nrom.cpu_read(0x6123), 0x55);
+        assert_eq!(nrom.save_ram().unwrap()[0x123], 0x55);
+    }
+
+    #[test]
+    fn test_no_prg_ram_unless_declared() {
+        let mut nrom = Nrom::new(test_rom());
+        nrom.cpu_write(0x6123, 0x55);
+
+        assert_eq!(nrom.cpu_read(0x6123), 0);
+        assert!(nrom.save_ram().is_none());
+    }
+
+    #[test]
+    fn test_chr_rom_is_read_only() {
+        let mut rom = test_rom();
+        rom.chr_rom[0x0100] = 0x11;
+        let mut nrom = Nrom::new(rom);
+        nrom.ppu_write(0x0100, 0x22);
+
+        assert_eq!(nrom.ppu_read(0x0100), 0x11);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut rom = test_rom();
+        rom.chr_rom = vec![];
+        let mut nrom = Nrom::new(rom);
+        nrom.ppu_write(0x1fff, 0x22);
+
+        assert_eq!(nrom.ppu_read(0x1fff), 0x22);
+    }
+}
@@ -0,0 +1,122 @@
+use super::DiscreteMemory;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Mapper 2: UxROM. A switchable 16K bank at $8000, the last bank fixed
+/// at $C000, CHR is usually RAM
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    memory: DiscreteMemory,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        let memory = DiscreteMemory::new(&rom);
+
+        Uxrom {
+            bus_conflicts: super::has_bus_conflicts(&rom, true),
+            prg_rom: rom.prg_rom,
+            memory,
+            mirroring: rom.screen_mirroring,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).max(1) - 1,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.memory.write_prg_ram(addr, data),
+            0x8000..=0xFFFF => {
+                self.prg_bank = if self.bus_conflicts {
+                    super::bus_conflict(&self.prg_rom, self.prg_offset(addr), data)
+                } else {
+                    data
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.memory.read_chr(addr as usize)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.memory.write_chr(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        self.memory.save_ram()
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        self.memory.save_ram_mut()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::test::{banks, cartridge};
+
+    fn uxrom(submapper: u8) -> Uxrom {
+        Uxrom::new(cartridge(submapper, banks(8, PRG_BANK_SIZE, 0xf0), vec![]))
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut uxrom = uxrom(1);
+        uxrom.cpu_write(0x8000, 3);
+        assert_eq!(uxrom.cpu_read(0x8000), 0xf3);
+        assert_eq!(uxrom.cpu_read(0xC000), 0xf7);
+    }
+
+    #[test]
+    fn test_prg_smaller_than_a_bank() {
+        let uxrom = Uxrom::new(cartridge(1, vec![0x42; 0x2000], vec![]));
+        assert_eq!(uxrom.cpu_peek(0xC000), 0x42);
+        assert_eq!(uxrom.cpu_peek(0xFFFF), 0x42);
+    }
+
+    #[test]
+    fn test_bus_conflict() {
+        let mut uxrom = uxrom(0);
+        // the ROM under $C000 holds 0xf7, so bit 3 is pulled low
+        uxrom.cpu_write(0xC000, 0x0d);
+        assert_eq!(uxrom.cpu_read(0x8000), 0xf5);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut uxrom = uxrom(0);
+        uxrom.ppu_write(0x1234, 0x55);
+        assert_eq!(uxrom.ppu_read(0x1234), 0x55);
+    }
+}
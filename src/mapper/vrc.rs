@@ -0,0 +1,293 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Mappers 21, 22, 23 and 25: Konami VRC2 and VRC4. The boards differ in
+/// which CPU address lines select the register within each $1000 block
+pub struct Vrc {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    /// Masks of the address lines wired to register bits 0 and 1. Dumps
+    /// without a submapper OR both variants of the mapper together
+    register_lines: (u16, u16),
+    vrc2: bool,
+    /// VRC2a drops the lowest CHR bank bit
+    chr_shift: u8,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        let (register_lines, vrc2) = match (rom.mapper, rom.submapper) {
+            // VRC4a, VRC4c
+            (21, 1) => ((0x02, 0x04), false),
+            (21, 2) => ((0x40, 0x80), false),
+            (21, _) => ((0x42, 0x84), false),
+            // VRC2a
+            (22, _) => ((0x02, 0x01), true),
+            // VRC4f, VRC4e, VRC2b
+            (23, 1) => ((0x01, 0x02), false),
+            (23, 2) => ((0x04, 0x08), false),
+            (23, 3) => ((0x01, 0x02), true),
+            (23, _) => ((0x05, 0x0a), false),
+            // VRC4b, VRC4d, VRC2c
+            (25, 1) => ((0x02, 0x01), false),
+            (25, 2) => ((0x08, 0x04), false),
+            (25, 3) => ((0x02, 0x01), true),
+            _ => ((0x0a, 0x05), false),
+        };
+
+        Vrc {
+            chr_shift: if rom.mapper == 22 { 1 } else { 0 },
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            register_lines,
+            vrc2,
+            prg_banks: [0, 1],
+            prg_swap: false,
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            mirroring: rom.screen_mirroring,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    /// Folds a CPU address into $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        let (bit0, bit1) = self.register_lines;
+        let mut register = addr & 0xf000;
+        if addr & bit0 != 0 {
+            register |= 1;
+        }
+        if addr & bit1 != 0 {
+            register |= 2;
+        }
+        register
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+        let last = banks - 1;
+        let bank = match (addr, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => (last + banks - 1) % banks,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => last,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = (self.chr_banks[addr as usize / CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        (bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn write_chr_bank(&mut self, register: u16, data: u8) {
+        // $B000-$E003 hold the low and high halves of two banks per block
+        let index = (register - 0xb000) as usize;
+        let bank = (index >> 12) * 2 + ((index & 0b10) >> 1);
+        let data = data as u16;
+
+        self.chr_banks[bank] = if index & 1 == 0 {
+            (self.chr_banks[bank] & 0x1f0) | (data & 0x0f)
+        } else {
+            (self.chr_banks[bank] & 0x00f) | ((data & 0x1f) << 4)
+        };
+    }
+}
+
+impl Mapper for Vrc {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => super::read_prg_ram(&self.prg_ram, addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            super::write_prg_ram(&mut self.prg_ram, addr, data);
+            return;
+        }
+        if addr < 0x8000 {
+            return;
+        }
+
+        match self.register(addr) {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1f,
+            0xA000..=0xA003 => self.prg_banks[1] = data & 0x1f,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002 | 0x9003 => self.prg_swap = data & 0b10 != 0,
+            register @ 0xB000..=0xEFFF => self.write_chr_bank(register, data),
+            0xF000..=0xF003 if self.vrc2 => {}
+            0xF000 => self.irq.write_latch_low(data),
+            0xF001 => self.irq.write_latch_high(data),
+            0xF002 => self.irq.write_control(data),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        self.irq.tick(cycles);
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn vrc(mapper: u16, submapper: u8) -> Vrc {
+        let mut rom = test_rom();
+        rom.mapper = mapper;
+        rom.submapper = submapper;
+        rom.prg_rom = (0..16)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..256)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        Vrc::new(rom)
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut vrc = vrc(21, 1);
+        vrc.cpu_write(0x8000, 3);
+        vrc.cpu_write(0xA000, 5);
+        assert_eq!(vrc.cpu_read(0x8000), 3);
+        assert_eq!(vrc.cpu_read(0xA000), 5);
+        assert_eq!(vrc.cpu_read(0xC000), 14);
+        assert_eq!(vrc.cpu_read(0xE000), 15);
+
+        // VRC4a puts $9002 on A2
+        vrc.cpu_write(0x9004, 0b10);
+        assert_eq!(vrc.cpu_read(0x8000), 14);
+        assert_eq!(vrc.cpu_read(0xC000), 3);
+    }
+
+    #[test]
+    fn test_prg_banking_8k() {
+        let mut rom = test_rom();
+        rom.mapper = 21;
+        rom.prg_rom = vec![7; PRG_BANK_SIZE];
+        let mut vrc = Vrc::new(rom);
+        vrc.cpu_write(0x9004, 0b10);
+        for addr in [0x8000, 0xA000, 0xC000, 0xE000] {
+            assert_eq!(vrc.cpu_read(addr), 7);
+        }
+
+        // NES 2.0 sizes can be smaller than a bank, which then repeats
+        let mut rom = test_rom();
+        rom.mapper = 21;
+        rom.prg_rom = vec![7; 0x1000];
+        let vrc = Vrc::new(rom);
+        assert_eq!(vrc.cpu_peek(0xC000), 7);
+        assert_eq!(vrc.cpu_peek(0xFFFF), 7);
+    }
+
+    #[test]
+    fn test_chr_banking() {
+        let mut vrc = vrc(25, 1);
+        // VRC4b swaps A0 and A1: $B001 is the low half of bank 1
+        vrc.cpu_write(0xB001, 0x0a);
+        vrc.cpu_write(0xB002, 0x0f);
+        vrc.cpu_write(0xE002, 0x03);
+        vrc.cpu_write(0xE003, 0x01);
+        assert_eq!(vrc.ppu_read(0x0000), 0xf0);
+        assert_eq!(vrc.ppu_read(0x0400), 0x0a);
+        assert_eq!(vrc.ppu_read(0x1800), 0x36);
+        assert_eq!(vrc.ppu_read(0x1c00), 0x17);
+    }
+
+    #[test]
+    fn test_vrc2a_chr_shift() {
+        let mut vrc = vrc(22, 0);
+        vrc.cpu_write(0xB000, 0x07);
+        assert_eq!(vrc.ppu_read(0x0000), 0x03);
+    }
+
+    #[test]
+    fn test_submapper_zero_combines_lines() {
+        let mut vrc = vrc(23, 0);
+        // VRC4e wiring: $F004 and $F008 reach $F001 and $F002
+        vrc.cpu_write(0xF000, 0x0f);
+        vrc.cpu_write(0xF004, 0x0f);
+        vrc.cpu_write(0xF008, 0b110);
+        vrc.cpu_tick(1);
+        assert!(vrc.irq());
+        // VRC4f wiring: $F003 acknowledges
+        vrc.cpu_write(0xF003, 0);
+        assert!(!vrc.irq());
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut vrc4 = vrc(21, 2);
+        vrc4.cpu_write(0x9000, 3);
+        assert_eq!(vrc4.mirroring(), Mirroring::SingleScreenUpper);
+
+        let mut vrc2 = vrc(23, 3);
+        vrc2.cpu_write(0x9000, 3);
+        assert_eq!(vrc2.mirroring(), Mirroring::Horizontal);
+    }
+}
@@ -0,0 +1,417 @@
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Rom;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+/// Two pulses at volume 15 plus the sawtooth at 31
+const MAX_AUDIO_LEVEL: f32 = 61.0;
+
+/// Mappers 24 and 26: Konami VRC6, with two extra pulse channels and a
+/// sawtooth channel. VRC6b (26) swaps the A0 and A1 register lines
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    swap_lines: bool,
+
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    chr_mode: u8,
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+
+    pulse: [Pulse; 2],
+    sawtooth: Sawtooth,
+    audio_halt: bool,
+    /// Frequency scaling from $9003, the periods are shifted right by it
+    period_shift: u8,
+}
+
+impl Vrc6 {
+    pub fn new(rom: Rom) -> Self {
+        let prg_ram = super::prg_ram(&rom);
+        let (chr, chr_is_ram) = super::chr_memory(&rom);
+
+        Vrc6 {
+            swap_lines: rom.mapper == 26,
+            prg_rom: rom.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0, 1, 2, 3, 4, 5, 6, 7],
+            chr_mode: 0,
+            mirroring: rom.screen_mirroring,
+            prg_ram_enabled: false,
+            irq: VrcIrq::default(),
+            pulse: [Pulse::default(), Pulse::default()],
+            sawtooth: Sawtooth::default(),
+            audio_halt: false,
+            period_shift: 0,
+        }
+    }
+
+    /// Folds a CPU address into $x000-$x003
+    fn register(&self, addr: u16) -> u16 {
+        let lines = addr & 0b11;
+        let lines = if self.swap_lines {
+            (lines >> 1) | ((lines & 1) << 1)
+        } else {
+            lines
+        };
+        (addr & 0xf000) | lines
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let last = (self.prg_rom.len() / PRG_BANK_SIZE).max(1) - 1;
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank_16k as usize * 2 + ((addr as usize >> 13) & 1),
+            0xC000..=0xDFFF => self.prg_bank_8k as usize,
+            _ => last,
+        };
+        (bank * PRG_BANK_SIZE + (addr as usize & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let slot = addr as usize / CHR_BANK_SIZE;
+        // in the 2K modes PPU A10 picks the half of the selected bank
+        let a10 = slot & 1;
+        let bank = match (self.chr_mode, slot) {
+            (0, _) => self.chr_banks[slot] as usize,
+            (1, _) => (self.chr_banks[slot / 2] as usize & !1) | a10,
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            _ => (self.chr_banks[4 + (slot - 4) / 2] as usize & !1) | a10,
+        };
+        (bank * CHR_BANK_SIZE + (addr as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => super::read_prg_ram(&self.prg_ram, addr),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled {
+                super::write_prg_ram(&mut self.prg_ram, addr, data);
+            }
+            return;
+        }
+        if addr < 0x8000 {
+            return;
+        }
+
+        match self.register(addr) {
+            0x8000..=0x8003 => self.prg_bank_16k = data & 0x0f,
+            register @ 0x9000..=0x9002 => self.pulse[0].write(register & 0b11, data),
+            0x9003 => {
+                self.audio_halt = data & 0x01 != 0;
+                self.period_shift = if data & 0x04 != 0 {
+                    8
+                } else if data & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            register @ 0xA000..=0xA002 => self.pulse[1].write(register & 0b11, data),
+            register @ 0xB000..=0xB002 => self.sawtooth.write(register & 0b11, data),
+            0xB003 => {
+                self.chr_mode = data & 0b11;
+                self.mirroring = match (data >> 2) & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.prg_ram_enabled = data & 0x80 != 0;
+            }
+            0xC000..=0xC003 => self.prg_bank_8k = data & 0x1f,
+            register @ 0xD000..=0xE003 => {
+                let bank = ((register - 0xd000) >> 12) * 4 + (register & 0b11);
+                self.chr_banks[bank as usize] = data;
+            }
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_offset(addr)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(addr);
+            self.chr[offset] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn cpu_tick(&mut self, cycles: u8) {
+        self.irq.tick(cycles);
+
+        if self.audio_halt {
+            return;
+        }
+        for _ in 0..cycles {
+            self.pulse[0].clock(self.period_shift);
+            self.pulse[1].clock(self.period_shift);
+            self.sawtooth.clock(self.period_shift);
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let level = self.pulse[0].output() + self.pulse[1].output() + self.sawtooth.output();
+        level as f32 / MAX_AUDIO_LEVEL
+    }
+
+    fn save_ram(&self) -> Option<&[u8]> {
+        super::save_ram(&self.prg_ram)
+    }
+
+    fn save_ram_mut(&mut self) -> Option<&mut [u8]> {
+        super::save_ram_mut(&mut self.prg_ram)
+    }
+}
+
+#[derive(Default)]
+struct Pulse {
+    volume: u8,
+    duty: u8,
+    /// Mode bit: output the volume constantly
+    ignore_duty: bool,
+    enabled: bool,
+    period: u16,
+    divider: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.volume = data & 0x0f;
+                self.duty = (data >> 4) & 0b111;
+                self.ignore_duty = data & 0x80 != 0;
+            }
+            1 => self.period = (self.period & 0x0f00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((data as u16 & 0x0f) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, period_shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.divider == 0 {
+            self.divider = self.period >> period_shift;
+            self.step = (self.step + 1) & 0x0f;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sawtooth {
+    rate: u8,
+    enabled: bool,
+    period: u16,
+    divider: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0x3f,
+            1 => self.period = (self.period & 0x0f00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00ff) | ((data as u16 & 0x0f) << 8);
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    /// The accumulator gains `rate` on every other step and is cleared on
+    /// the 14th
+    fn clock(&mut self, period_shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+
+        self.divider = self.period >> period_shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 1 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rom::test::test_rom;
+
+    fn vrc6(mapper: u16) -> Vrc6 {
+        let mut rom = test_rom();
+        rom.mapper = mapper;
+        rom.prg_rom = (0..16)
+            .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+            .collect();
+        rom.chr_rom = (0..32)
+            .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+            .collect();
+        Vrc6::new(rom)
+    }
+
+    #[test]
+    fn test_prg_banking() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0x8000, 2);
+        vrc6.cpu_write(0xC000, 9);
+        assert_eq!(vrc6.cpu_read(0x8000), 4);
+        assert_eq!(vrc6.cpu_read(0xA000), 5);
+        assert_eq!(vrc6.cpu_read(0xC000), 9);
+        assert_eq!(vrc6.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_prg_smaller_than_a_bank() {
+        let mut rom = test_rom();
+        rom.mapper = 24;
+        rom.prg_rom = vec![0x42; 0x1000];
+        let vrc6 = Vrc6::new(rom);
+        assert_eq!(vrc6.cpu_peek(0xE000), 0x42);
+        assert_eq!(vrc6.cpu_peek(0xFFFF), 0x42);
+    }
+
+    #[test]
+    fn test_chr_banking() {
+        let mut vrc6 = vrc6(26);
+        vrc6.cpu_write(0xD000, 10);
+        // VRC6b: $E002 is register 1 of the second block
+        vrc6.cpu_write(0xE002, 21);
+        assert_eq!(vrc6.ppu_read(0x0000), 10);
+        assert_eq!(vrc6.ppu_read(0x1400), 21);
+
+        // 2K banks for the upper pattern table
+        vrc6.cpu_write(0xB003, 0x02);
+        vrc6.cpu_write(0xE000, 21);
+        assert_eq!(vrc6.ppu_read(0x1000), 20);
+        assert_eq!(vrc6.ppu_read(0x1400), 21);
+    }
+
+    #[test]
+    fn test_mirroring_and_prg_ram() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0x6000, 0x55);
+        assert_eq!(vrc6.cpu_read(0x6000), 0);
+
+        vrc6.cpu_write(0xB003, 0x84);
+        vrc6.cpu_write(0x6000, 0x55);
+        assert_eq!(vrc6.cpu_read(0x6000), 0x55);
+        assert_eq!(vrc6.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0xF000, 0xfe);
+        vrc6.cpu_write(0xF001, 0b110);
+        vrc6.cpu_tick(2);
+        assert!(vrc6.irq());
+        vrc6.cpu_write(0xF002, 0);
+        assert!(!vrc6.irq());
+    }
+
+    #[test]
+    fn test_pulse_output() {
+        let mut vrc6 = vrc6(24);
+        // volume 15, duty 1/16, period 0
+        vrc6.cpu_write(0x9000, 0x0f);
+        vrc6.cpu_write(0x9001, 0x00);
+        vrc6.cpu_write(0x9002, 0x80);
+        assert_eq!(vrc6.audio_output(), 15.0 / MAX_AUDIO_LEVEL);
+
+        vrc6.cpu_tick(1);
+        assert_eq!(vrc6.audio_output(), 0.0);
+        vrc6.cpu_tick(15);
+        assert_eq!(vrc6.audio_output(), 15.0 / MAX_AUDIO_LEVEL);
+
+        // mode bit outputs the volume regardless of duty
+        vrc6.cpu_write(0x9000, 0x8f);
+        vrc6.cpu_tick(3);
+        assert_eq!(vrc6.audio_output(), 15.0 / MAX_AUDIO_LEVEL);
+    }
+
+    #[test]
+    fn test_sawtooth_output() {
+        let mut vrc6 = vrc6(24);
+        vrc6.cpu_write(0xB000, 0x20);
+        vrc6.cpu_write(0xB001, 0x00);
+        vrc6.cpu_write(0xB002, 0x80);
+
+        vrc6.cpu_tick(2);
+        assert_eq!(vrc6.sawtooth.output(), 4);
+        vrc6.cpu_tick(10);
+        assert_eq!(vrc6.sawtooth.output(), 24);
+        vrc6.cpu_tick(2);
+        assert_eq!(vrc6.sawtooth.output(), 0);
+
+        vrc6.cpu_write(0x9003, 0x01);
+        vrc6.cpu_tick(2);
+        assert_eq!(vrc6.sawtooth.output(), 0);
+    }
+}
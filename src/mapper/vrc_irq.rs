@@ -0,0 +1,114 @@
+/// CPU cycles per scanline, times 3 so the prescaler stays an integer
+const PRESCALER_RELOAD: i16 = 341;
+
+/// The IRQ counter shared by VRC4 and VRC6. It counts up to $FF either
+/// every CPU cycle or, through a prescaler, once per scanline
+#[derive(Default)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    /// VRC4 loads the latch a nibble at a time
+    pub fn write_latch_low(&mut self, data: u8) {
+        self.latch = (self.latch & 0xf0) | (data & 0x0f);
+    }
+
+    pub fn write_latch_high(&mut self, data: u8) {
+        self.latch = (self.latch & 0x0f) | (data << 4);
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & 0x01 != 0;
+        self.enabled = data & 0x02 != 0;
+        self.cycle_mode = data & 0x04 != 0;
+        self.pending = false;
+
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_RELOAD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        for _ in 0..cycles {
+            if self.cycle_mode {
+                self.clock_counter();
+            } else {
+                self.prescaler -= 3;
+                if self.prescaler <= 0 {
+                    self.prescaler += PRESCALER_RELOAD;
+                    self.clock_counter();
+                }
+            }
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cycle_mode() {
+        let mut irq = VrcIrq::default();
+        irq.write_latch(0xfd);
+        irq.write_control(0b110);
+
+        irq.tick(2);
+        assert!(!irq.pending());
+        irq.tick(1);
+        assert!(irq.pending());
+
+        irq.acknowledge();
+        assert!(!irq.pending());
+        irq.tick(10);
+        assert!(!irq.pending());
+    }
+
+    #[test]
+    fn test_scanline_mode() {
+        let mut irq = VrcIrq::default();
+        irq.write_latch_low(0x0e);
+        irq.write_latch_high(0x0f);
+        irq.write_control(0b010);
+
+        // two scanlines are 227.3 CPU cycles
+        irq.tick(200);
+        assert!(!irq.pending());
+        irq.tick(28);
+        assert!(irq.pending());
+    }
+}
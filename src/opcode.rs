@@ -13,11 +13,11 @@ pub struct OpCode {
 impl OpCode {
     pub fn new(code:u8, mnemonic: &'static str, len: u8, cycles:u8, mode: AddressingMode) -> Self {
         OpCode {
-            code: code,
-            mnemonic: mnemonic,
-            len: len,
-            cycles: cycles,
-            mode: mode,
+            code,
+            mnemonic,
+            len,
+            cycles,
+            mode,
         }
     }
 }
@@ -84,7 +84,7 @@ lazy_static! {
 
         OpCode::new(0xc0, "CPY", 2, 2, AddressingMode::Immediate),
         OpCode::new(0xc4, "CPY", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xec, "CPY", 3, 4, AddressingMode::Absolute),
+        OpCode::new(0xcc, "CPY", 3, 4, AddressingMode::Absolute),
 
         OpCode::new(0xc6, "DEC", 2, 5, AddressingMode::ZeroPage),
         OpCode::new(0xd6, "DEC", 2, 6, AddressingMode::ZeroPage_X),
@@ -111,8 +111,8 @@ lazy_static! {
         OpCode::new(0xe8, "INX", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0xc8, "INY", 1, 2, AddressingMode::NoneAddressing),
 
-        OpCode::new(0x4c, "JMP abs", 3, 3, AddressingMode::Absolute),
-        OpCode::new(0x6c, "JMP ind", 3, 5, AddressingMode::Immediate),
+        OpCode::new(0x4c, "JMP", 3, 3, AddressingMode::NoneAddressing), //AddressingMode that acts as Immidiate
+        OpCode::new(0x6c, "JMP", 3, 5, AddressingMode::NoneAddressing), //AddressingMode:Indirect with 6502 bug
 
         OpCode::new(0x20, "JSR", 3, 6, AddressingMode::NoneAddressing),
 
@@ -127,9 +127,9 @@ lazy_static! {
 
         OpCode::new(0xa2, "LDX", 2, 2, AddressingMode::Immediate),
         OpCode::new(0xa6, "LDX", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xb6, "LDX", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0xb6, "LDX", 2, 4, AddressingMode::ZeroPage_Y),
         OpCode::new(0xae, "LDX", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xbe, "LDX", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0xbe, "LDX", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_Y),
 
         OpCode::new(0xa0, "LDY", 2, 2, AddressingMode::Immediate),
         OpCode::new(0xa4, "LDY", 2, 3, AddressingMode::ZeroPage),
@@ -171,7 +171,7 @@ lazy_static! {
         OpCode::new(0x6e, "ROR", 3, 6, AddressingMode::Absolute),
         OpCode::new(0x7e, "ROR", 3, 7, AddressingMode::Absolute_X),
 
-        OpCode::new(0x40, "RIT", 1, 6, AddressingMode::NoneAddressing),
+        OpCode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
         OpCode::new(0x60, "RTS", 1, 6, AddressingMode::NoneAddressing),
 
         OpCode::new(0xe9, "SBC", 2, 2, AddressingMode::Immediate),
@@ -201,7 +201,7 @@ lazy_static! {
         OpCode::new(0x8e, "STX", 3, 4, AddressingMode::Absolute),
 
         OpCode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_Y),
+        OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPage_X),
         OpCode::new(0x8c, "STY", 3, 4, AddressingMode::Absolute),
 
         OpCode::new(0xaa, "TAX", 1, 2, AddressingMode::NoneAddressing),
@@ -210,6 +210,133 @@ lazy_static! {
         OpCode::new(0x8a, "TXA", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x9a, "TXS", 1, 2, AddressingMode::NoneAddressing),
         OpCode::new(0x98, "TYA", 1, 2, AddressingMode::NoneAddressing),
+
+        /* Unofficial opcodes */
+        OpCode::new(0x1a, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x3a, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x5a, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x7a, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xda, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xfa, "*NOP", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x80, "*NOP", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0x82, "*NOP", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0x89, "*NOP", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0xc2, "*NOP", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0xe2, "*NOP", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0x04, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x44, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x64, "*NOP", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x14, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0x34, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0x54, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0x74, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0xd4, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0xf4, "*NOP", 2, 4, AddressingMode::ZeroPage_X),
+        OpCode::new(0x0c, "*NOP", 3, 4, AddressingMode::Absolute),
+        OpCode::new(0x1c, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0x3c, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0x5c, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0x7c, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0xdc, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+        OpCode::new(0xfc, "*NOP", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_X),
+
+        OpCode::new(0x02, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x12, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x22, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x32, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x42, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x52, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x62, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x72, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0x92, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xb2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xd2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+        OpCode::new(0xf2, "*JAM", 1, 2, AddressingMode::NoneAddressing),
+
+        OpCode::new(0xa7, "*LAX", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0xb7, "*LAX", 2, 4, AddressingMode::ZeroPage_Y),
+        OpCode::new(0xaf, "*LAX", 3, 4, AddressingMode::Absolute),
+        OpCode::new(0xbf, "*LAX", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_Y),
+        OpCode::new(0xa3, "*LAX", 2, 6, AddressingMode::Indirect_X),
+        OpCode::new(0xb3, "*LAX", 2, 5,/*+1 if page crossed */ AddressingMode::Indirect_Y),
+        OpCode::new(0xab, "*LAX", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0x87, "*SAX", 2, 3, AddressingMode::ZeroPage),
+        OpCode::new(0x97, "*SAX", 2, 4, AddressingMode::ZeroPage_Y),
+        OpCode::new(0x8f, "*SAX", 3, 4, AddressingMode::Absolute),
+        OpCode::new(0x83, "*SAX", 2, 6, AddressingMode::Indirect_X),
+
+        OpCode::new(0xeb, "*SBC", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0xc7, "*DCP", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0xd7, "*DCP", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0xcf, "*DCP", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0xdf, "*DCP", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0xdb, "*DCP", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0xc3, "*DCP", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0xd3, "*DCP", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0xe7, "*ISB", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0xf7, "*ISB", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0xef, "*ISB", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0xff, "*ISB", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0xfb, "*ISB", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0xe3, "*ISB", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0xf3, "*ISB", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x07, "*SLO", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0x17, "*SLO", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0x0f, "*SLO", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0x1f, "*SLO", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0x1b, "*SLO", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0x03, "*SLO", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0x13, "*SLO", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x27, "*RLA", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0x37, "*RLA", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0x2f, "*RLA", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0x3f, "*RLA", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0x3b, "*RLA", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0x23, "*RLA", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0x33, "*RLA", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x47, "*SRE", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0x57, "*SRE", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0x4f, "*SRE", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0x5f, "*SRE", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0x5b, "*SRE", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0x43, "*SRE", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0x53, "*SRE", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x67, "*RRA", 2, 5, AddressingMode::ZeroPage),
+        OpCode::new(0x77, "*RRA", 2, 6, AddressingMode::ZeroPage_X),
+        OpCode::new(0x6f, "*RRA", 3, 6, AddressingMode::Absolute),
+        OpCode::new(0x7f, "*RRA", 3, 7, AddressingMode::Absolute_X),
+        OpCode::new(0x7b, "*RRA", 3, 7, AddressingMode::Absolute_Y),
+        OpCode::new(0x63, "*RRA", 2, 8, AddressingMode::Indirect_X),
+        OpCode::new(0x73, "*RRA", 2, 8, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x0b, "*ANC", 2, 2, AddressingMode::Immediate),
+        OpCode::new(0x2b, "*ANC", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0x4b, "*ALR", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0x6b, "*ARR", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0xcb, "*AXS", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0x8b, "*XAA", 2, 2, AddressingMode::Immediate),
+
+        OpCode::new(0x9f, "*SHA", 3, 5, AddressingMode::Absolute_Y),
+        OpCode::new(0x93, "*SHA", 2, 6, AddressingMode::Indirect_Y),
+
+        OpCode::new(0x9e, "*SHX", 3, 5, AddressingMode::Absolute_Y),
+
+        OpCode::new(0x9c, "*SHY", 3, 5, AddressingMode::Absolute_X),
+
+        OpCode::new(0x9b, "*TAS", 3, 5, AddressingMode::Absolute_Y),
+
+        OpCode::new(0xbb, "*LAS", 3, 4,/*+1 if page crossed */ AddressingMode::Absolute_Y),
     ];
 
     pub static ref OPCODES_MAP: HashMap<u8, &'static OpCode> = {
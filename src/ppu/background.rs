@@ -0,0 +1,273 @@
+use super::registers::MaskRegister;
+use super::PPU;
+use super::{COARSE_X, COARSE_Y, FINE_Y, NAMETABLE_X, NAMETABLE_Y};
+use crate::mapper::Mapper;
+
+/// Latches and shift registers of the background pipeline. Each tile is
+/// fetched over 8 dots and shifted out one pixel per dot, two tiles ahead
+#[derive(Default)]
+pub(super) struct Background {
+    tile: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_shift_low: u16,
+    pattern_shift_high: u16,
+    attribute_shift_low: u16,
+    attribute_shift_high: u16,
+}
+
+impl PPU {
+    /// Fetches and shifts for one dot of a visible or pre-render scanline,
+    /// following the 2C02's memory access pattern so mappers watching the bus
+    /// see the same reads
+    pub(super) fn background_dot(&mut self, mapper: &mut dyn Mapper) {
+        let dot = self.dot;
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+            if dot % 8 == 1 {
+                self.reload_background();
+            }
+        }
+
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match (dot - 1) % 8 {
+                0 => self.fetch_tile(mapper),
+                2 => self.fetch_attribute(mapper),
+                4 => self.fetch_pattern(mapper, 0),
+                6 => self.fetch_pattern(mapper, 8),
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                let horizontal = COARSE_X | NAMETABLE_X;
+                self.v = (self.v & !horizontal) | (self.t & horizontal);
+            }
+            280..=304 if self.scanline == self.pre_render_scanline() => {
+                let vertical = FINE_Y | NAMETABLE_Y | COARSE_Y;
+                self.v = (self.v & !vertical) | (self.t & vertical);
+            }
+            // unused nametable fetches, which MMC5 counts scanlines with
+            337 | 339 => self.fetch_tile(mapper),
+            _ => {}
+        }
+    }
+
+    /// Palette index of the background at the current dot, 0 if transparent
+    pub(super) fn background_pixel(&self) -> u8 {
+        let x = self.dot - 1;
+        if !self.mask.contains(MaskRegister::SHOW_BACKGROUND)
+            || (x < 8 && !self.mask.contains(MaskRegister::SHOW_BACKGROUND_LEFT))
+        {
+            return 0;
+        }
+
+        let bit = 0x8000 >> self.fine_x;
+        let bg = &self.background;
+        let pattern = (bg.pattern_shift_low & bit != 0) as u8
+            | ((bg.pattern_shift_high & bit != 0) as u8) << 1;
+        if pattern == 0 {
+            return 0;
+        }
+        let attribute = (bg.attribute_shift_low & bit != 0) as u8
+            | ((bg.attribute_shift_high & bit != 0) as u8) << 1;
+        attribute << 2 | pattern
+    }
+
+    fn shift_background(&mut self) {
+        let bg = &mut self.background;
+        bg.pattern_shift_low <<= 1;
+        bg.pattern_shift_high <<= 1;
+        bg.attribute_shift_low <<= 1;
+        bg.attribute_shift_high <<= 1;
+    }
+
+    fn reload_background(&mut self) {
+        let bg = &mut self.background;
+        bg.pattern_shift_low = (bg.pattern_shift_low & 0xff00) | bg.pattern_low as u16;
+        bg.pattern_shift_high = (bg.pattern_shift_high & 0xff00) | bg.pattern_high as u16;
+        let low = if bg.attribute & 0b01 != 0 { 0xff } else { 0x00 };
+        let high = if bg.attribute & 0b10 != 0 { 0xff } else { 0x00 };
+        bg.attribute_shift_low = (bg.attribute_shift_low & 0xff00) | low;
+        bg.attribute_shift_high = (bg.attribute_shift_high & 0xff00) | high;
+    }
+
+    fn fetch_tile(&mut self, mapper: &mut dyn Mapper) {
+        let addr = 0x2000 | (self.v & 0x0fff);
+        self.background.tile = self.read_nametable(addr, mapper);
+    }
+
+    fn fetch_attribute(&mut self, mapper: &mut dyn Mapper) {
+        let v = self.v;
+        let addr = 0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+        let attribute = self.read_nametable(addr, mapper);
+        // each attribute byte covers 4x4 tiles, two bits per 2x2 quadrant
+        let shift = ((v >> 4) & 0x04) | (v & 0x02);
+        self.background.attribute = (attribute >> shift) & 0b11;
+    }
+
+    fn fetch_pattern(&mut self, mapper: &mut dyn Mapper, plane: u16) {
+        let fine_y = (self.v & FINE_Y) >> 12;
+        let addr =
+            self.ctrl.background_pattern_addr() + self.background.tile as u16 * 16 + plane + fine_y;
+        let data = mapper.ppu_read(addr);
+        if plane == 0 {
+            self.background.pattern_low = data;
+        } else {
+            self.background.pattern_high = data;
+        }
+    }
+
+    pub(super) fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == COARSE_X {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    pub(super) fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !FINE_Y;
+        let coarse_y = (self.v & COARSE_Y) >> 5;
+        let coarse_y = match coarse_y {
+            // the last row of tiles, the attribute table follows
+            29 => {
+                self.v ^= NAMETABLE_Y;
+                0
+            }
+            // rows 30 and 31 are reachable by scrolling, they wrap without
+            // switching nametables
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{run_frames, run_to, set_scroll, write_vram};
+    use super::*;
+    use crate::mapper;
+    use crate::rom::test::test_rom;
+
+    /// Tile 1 is solid colour 1, tile 2 solid colour 3. The nametable starts
+    /// with tile 1 and is tile 0 elsewhere
+    fn setup() -> (PPU, Box<dyn Mapper>) {
+        let mut rom = test_rom();
+        for row in 0..8 {
+            rom.chr_rom[16 + row] = 0xff;
+            rom.chr_rom[32 + row] = 0xff;
+            rom.chr_rom[40 + row] = 0xff;
+        }
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(rom);
+
+        write_vram(&mut ppu, mapper.as_mut(), 0x2000, &[1]);
+        write_vram(&mut ppu, mapper.as_mut(), 0x3f00, &[0x0f, 0x30, 0x00, 0x16]);
+        ppu.write_register(0x2001, 0b0000_1010, mapper.as_mut());
+        set_scroll(&mut ppu, mapper.as_mut(), 0, 0);
+        (ppu, mapper)
+    }
+
+    #[test]
+    fn test_tiles() {
+        let (mut ppu, mut mapper) = setup();
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(0, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(7, 7), 0x30);
+        assert_eq!(ppu.frame.pixel(8, 0), 0x0f);
+        assert_eq!(ppu.frame.pixel(0, 8), 0x0f);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let (mut ppu, mut mapper) = setup();
+        write_vram(&mut ppu, mapper.as_mut(), 0x2042, &[2, 2]);
+        write_vram(&mut ppu, mapper.as_mut(), 0x2044, &[2]);
+        // bottom right quadrant of the first attribute byte uses palette 1
+        write_vram(&mut ppu, mapper.as_mut(), 0x23c0, &[0b0100_0000]);
+        write_vram(&mut ppu, mapper.as_mut(), 0x3f07, &[0x27]);
+        set_scroll(&mut ppu, mapper.as_mut(), 0, 0);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(0, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(16, 16), 0x27);
+        assert_eq!(ppu.frame.pixel(31, 23), 0x27);
+        assert_eq!(ppu.frame.pixel(32, 16), 0x16);
+        assert_eq!(ppu.frame.pixel(16, 15), 0x0f);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let (mut ppu, mut mapper) = setup();
+        set_scroll(&mut ppu, mapper.as_mut(), 4, 2);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(3, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(4, 0), 0x0f);
+        assert_eq!(ppu.frame.pixel(0, 5), 0x30);
+        assert_eq!(ppu.frame.pixel(0, 6), 0x0f);
+    }
+
+    #[test]
+    fn test_scroll_wraps_into_next_nametable() {
+        let (mut ppu, mut mapper) = setup();
+        // horizontal mirroring: $2400 shows $2000
+        set_scroll(&mut ppu, mapper.as_mut(), 252, 0);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(3, 0), 0x0f);
+        assert_eq!(ppu.frame.pixel(4, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(11, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(12, 0), 0x0f);
+    }
+
+    #[test]
+    fn test_left_column_mask() {
+        let (mut ppu, mut mapper) = setup();
+        ppu.write_register(0x2001, 0b0000_1000, mapper.as_mut());
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(7, 0), 0x0f);
+    }
+
+    #[test]
+    fn test_rendering_disabled() {
+        let (mut ppu, mut mapper) = setup();
+        ppu.write_register(0x2001, 0, mapper.as_mut());
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert!(ppu.frame.data.iter().all(|&color| color == 0x0f));
+    }
+
+    #[test]
+    fn test_mid_frame_vram_addr_write() {
+        let (mut ppu, mut mapper) = setup();
+        run_frames(&mut ppu, mapper.as_mut(), 1);
+
+        // pointing v back at the top row in hblank restarts the nametable there
+        run_to(&mut ppu, mapper.as_mut(), 100, 300);
+        ppu.write_register(0x2006, 0x00, mapper.as_mut());
+        ppu.write_register(0x2006, 0x00, mapper.as_mut());
+        run_to(&mut ppu, mapper.as_mut(), 240, 0);
+
+        assert_eq!(ppu.frame.pixel(0, 0), 0x30);
+        assert_eq!(ppu.frame.pixel(0, 100), 0x0f);
+        assert_eq!(ppu.frame.pixel(0, 101), 0x30);
+        assert_eq!(ppu.frame.pixel(0, 108), 0x30);
+        assert_eq!(ppu.frame.pixel(0, 109), 0x0f);
+    }
+}
@@ -0,0 +1,688 @@
+mod background;
+pub mod frame;
+pub mod registers;
+mod sprites;
+
+use crate::mapper::Mapper;
+use crate::rom::Mirroring;
+use crate::rom::Timing;
+use background::Background;
+use frame::Frame;
+use registers::ControlRegister;
+use registers::MaskRegister;
+use registers::StatusRegister;
+use sprites::Sprites;
+
+/// 2K of console VRAM, four-screen cartridges bring their own for the rest
+const VRAM_SIZE: usize = 0x800;
+const NAMETABLE_SIZE: u16 = 0x400;
+const PALETTE_SIZE: usize = 32;
+const OAM_SIZE: usize = 256;
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+pub const PAL_SCANLINES_PER_FRAME: u16 = 312;
+const VISIBLE_SCANLINES: u16 = 240;
+const VBLANK_SCANLINE: u16 = 241;
+
+// layout of v and t
+const COARSE_X: u16 = 0x001f;
+const COARSE_Y: u16 = 0x03e0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+
+const PATTERN_TABLES_END: u16 = 0x1FFF;
+const NAMETABLES: u16 = 0x2000;
+const NAMETABLES_MIRRORS_END: u16 = 0x3EFF;
+const PALETTE: u16 = 0x3F00;
+
+/// The picture processing unit as seen through its eight CPU registers.
+/// Pattern tables live on the cartridge and are reached through the mapper,
+/// which is passed in for every access
+pub struct PPU {
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub oam_addr: u8,
+    pub oam_data: [u8; OAM_SIZE],
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: Frame,
+    vram: [u8; VRAM_SIZE],
+    palette_table: [u8; PALETTE_SIZE],
+    /// Current VRAM address (loopy v). PPUDATA goes through it, rendering
+    /// fetches through it as coarse X/Y, nametable and fine Y
+    v: u16,
+    /// Temporary VRAM address (loopy t), the scroll position v is reloaded from
+    t: u16,
+    /// Fine X scroll, the pixel within the tile
+    fine_x: u8,
+    /// First/second write toggle shared by $2005 and $2006
+    w: bool,
+    /// PPUDATA reads below the palette return the previous read
+    read_buffer: u8,
+    /// Last value driven on the CPU data bus, what write-only registers read as
+    open_bus: u8,
+    background: Background,
+    sprites: Sprites,
+    /// 262 lines on NTSC, 312 on PAL, the last one being pre-render
+    scanlines_per_frame: u16,
+    /// NTSC drops the last pre-render dot of every other rendered frame
+    skips_odd_dot: bool,
+    odd_frame: bool,
+    /// Set when vblank starts with NMI enabled, until the bus picks it up
+    nmi: bool,
+    /// Set when vblank starts, until the frontend picks the frame up
+    frame_complete: bool,
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        Self::with_timing(Timing::Ntsc)
+    }
+
+    pub fn with_timing(timing: Timing) -> Self {
+        let pal = timing == Timing::Pal;
+        PPU {
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            oam_addr: 0,
+            oam_data: [0; OAM_SIZE],
+            scanline: 0,
+            dot: 0,
+            frame: Frame::new(),
+            vram: [0; VRAM_SIZE],
+            palette_table: [0; PALETTE_SIZE],
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            read_buffer: 0,
+            open_bus: 0,
+            background: Background::default(),
+            sprites: Sprites::default(),
+            scanlines_per_frame: if pal {
+                PAL_SCANLINES_PER_FRAME
+            } else {
+                SCANLINES_PER_FRAME
+            },
+            skips_odd_dot: !pal,
+            odd_frame: false,
+            nmi: false,
+            frame_complete: false,
+        }
+    }
+
+    /// Advances the PPU by one dot
+    pub fn tick(&mut self, mapper: &mut dyn Mapper) {
+        let visible = self.scanline < VISIBLE_SCANLINES;
+        let pre_render = self.scanline == self.pre_render_scanline();
+        if pre_render && self.dot == 1 {
+            self.status.remove(
+                StatusRegister::VBLANK_STARTED
+                    | StatusRegister::SPRITE_ZERO_HIT
+                    | StatusRegister::SPRITE_OVERFLOW,
+            );
+        }
+        if self.scanline == VBLANK_SCANLINE && self.dot == 1 {
+            self.status.insert(StatusRegister::VBLANK_STARTED);
+            self.nmi |= self.ctrl.contains(ControlRegister::GENERATE_NMI);
+            self.frame_complete = true;
+        }
+        if self.rendering_enabled() && self.rendering_line() {
+            self.background_dot(mapper);
+            self.sprite_dot(mapper);
+        }
+        if visible && (1..=256).contains(&self.dot) {
+            self.output_pixel();
+        }
+
+        self.dot += 1;
+        if pre_render
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.skips_odd_dot
+            && self.rendering_enabled()
+        {
+            self.dot = DOTS_PER_SCANLINE;
+        }
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == self.scanlines_per_frame {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    /// Whether an NMI was raised since the last call
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi)
+    }
+
+    /// Whether a frame finished since the last call, `frame` then holds it
+    /// until the next one starts
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    fn pre_render_scanline(&self) -> u16 {
+        self.scanlines_per_frame - 1
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES)
+    }
+
+    /// Picks the background or sprite pixel for the current dot, raising
+    /// sprite 0 hit where both are opaque
+    fn output_pixel(&mut self) {
+        let pixel = if self.rendering_enabled() {
+            let background = self.background_pixel();
+            match self.sprite_pixel() {
+                Some(sprite) => {
+                    if sprite.sprite_zero && background != 0 && self.dot != 256 {
+                        self.status.insert(StatusRegister::SPRITE_ZERO_HIT);
+                    }
+                    if background != 0 && sprite.behind_background {
+                        background
+                    } else {
+                        0x10 | sprite.color
+                    }
+                }
+                None => background,
+            }
+        } else {
+            0
+        };
+
+        let mut color = self.palette_table[pixel as usize];
+        if self.mask.contains(MaskRegister::GREYSCALE) {
+            color &= 0x30;
+        }
+        let x = self.dot as usize - 1;
+        self.frame.set_pixel(x, self.scanline as usize, color);
+    }
+
+    fn rendering_line(&self) -> bool {
+        self.scanline < VISIBLE_SCANLINES || self.scanline == self.pre_render_scanline()
+    }
+
+    /// CPU write to $2000-$3FFF, the eight registers repeat every 8 bytes
+    pub fn write_register(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
+        self.open_bus = data;
+        match addr & 0x0007 {
+            0 => {
+                let ctrl = ControlRegister::from_bits_truncate(data);
+                // enabling NMI in the middle of vblank fires it straight away
+                if !self.ctrl.contains(ControlRegister::GENERATE_NMI)
+                    && ctrl.contains(ControlRegister::GENERATE_NMI)
+                    && self.status.contains(StatusRegister::VBLANK_STARTED)
+                {
+                    self.nmi = true;
+                }
+                self.ctrl = ctrl;
+                self.t = (self.t & !(NAMETABLE_X | NAMETABLE_Y)) | self.ctrl.nametable_bits();
+            }
+            1 => self.mask = MaskRegister::from_bits_truncate(data),
+            2 => {}
+            3 => self.oam_addr = data,
+            4 => self.write_oam(data),
+            5 => {
+                let data = data as u16;
+                if self.w {
+                    self.t =
+                        (self.t & !(FINE_Y | COARSE_Y)) | (data & 0x07) << 12 | (data >> 3) << 5;
+                } else {
+                    self.t = (self.t & !COARSE_X) | data >> 3;
+                    self.fine_x = (data & 0x07) as u8;
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = (self.t & 0xff00) | data as u16;
+                    self.v = self.t;
+                } else {
+                    // bit 14 of t is cleared along with the high byte
+                    self.t = (self.t & 0x00ff) | (data as u16 & 0x3f) << 8;
+                }
+                self.w = !self.w;
+            }
+            _ => {
+                self.write_vram(self.v, data, mapper);
+                self.increment_vram_addr();
+            }
+        }
+    }
+
+    /// OAMDATA write, also how OAM DMA fills OAM
+    pub fn write_oam(&mut self, data: u8) {
+        self.oam_data[self.oam_addr as usize] = data;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    /// CPU read from $2000-$3FFF, including the side effects of PPUSTATUS
+    /// and PPUDATA
+    pub fn read_register(&mut self, addr: u16, mapper: &mut dyn Mapper) -> u8 {
+        let data = match addr & 0x0007 {
+            2 => {
+                let data = self.peek_register(addr);
+                self.status.remove(StatusRegister::VBLANK_STARTED);
+                self.w = false;
+                data
+            }
+            4 => self.peek_register(addr),
+            7 => {
+                let addr = self.v & 0x3fff;
+                let data = if addr >= PALETTE {
+                    // palette reads are immediate, the buffer picks up the
+                    // nametable byte underneath instead
+                    self.read_buffer = self.read_vram(addr - 0x1000, mapper);
+                    self.palette_table[Self::palette_index(addr)] | (self.open_bus & 0xc0)
+                } else {
+                    let buffered = self.read_buffer;
+                    self.read_buffer = self.read_vram(addr, mapper);
+                    buffered
+                };
+                self.increment_vram_addr();
+                data
+            }
+            _ => self.open_bus,
+        };
+        self.open_bus = data;
+        data
+    }
+
+    /// What a register read would return, without any of its side effects
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr & 0x0007 {
+            2 => self.status.bits() | (self.open_bus & 0x1f),
+            // bits 2-4 of the attribute byte do not exist
+            4 if self.oam_addr & 0b11 == 2 => self.oam_data[self.oam_addr as usize] & 0xe3,
+            4 => self.oam_data[self.oam_addr as usize],
+            7 if self.v & 0x3fff >= PALETTE => {
+                self.palette_table[Self::palette_index(self.v)] | (self.open_bus & 0xc0)
+            }
+            7 => self.read_buffer,
+            _ => self.open_bus,
+        }
+    }
+
+    /// PPUDATA steps v by 1 or 32. While rendering the access collides with
+    /// the fetches and bumps coarse X and Y instead
+    fn increment_vram_addr(&mut self) {
+        if self.rendering_enabled() && self.rendering_line() {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            self.v = self.v.wrapping_add(self.ctrl.vram_addr_increment()) & 0x7fff;
+        }
+    }
+
+    /// Translates a nametable address into VRAM through the cartridge's
+    /// mirroring. $3000-$3EFF repeats $2000-$2EFF. Four-screen cartridges
+    /// serve the last two tables themselves, the first two are laid out
+    /// like vertical mirroring
+    fn vram_index(addr: u16, mirroring: Mirroring) -> usize {
+        let addr = addr & 0x0fff;
+        let table = addr / NAMETABLE_SIZE;
+        let page = match (mirroring, table) {
+            (Mirroring::Vertical | Mirroring::FourScreen, 0 | 2) => 0,
+            (Mirroring::Vertical | Mirroring::FourScreen, _) => 1,
+            (Mirroring::Horizontal, 0 | 1) => 0,
+            (Mirroring::Horizontal, _) => 1,
+            (Mirroring::SingleScreenLower, _) => 0,
+            (Mirroring::SingleScreenUpper, _) => 1,
+        };
+        (page * NAMETABLE_SIZE + addr % NAMETABLE_SIZE) as usize
+    }
+
+    /// Boards with their own nametable logic answer first, everything else
+    /// lands in VRAM
+    fn read_nametable(&mut self, addr: u16, mapper: &mut dyn Mapper) -> u8 {
+        let addr = NAMETABLES | (addr & 0x0fff);
+        match mapper.nametable_read(addr) {
+            Some(data) => data,
+            None => self.vram[Self::vram_index(addr, mapper.mirroring())],
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
+        let addr = NAMETABLES | (addr & 0x0fff);
+        if !mapper.nametable_write(addr, data) {
+            self.vram[Self::vram_index(addr, mapper.mirroring())] = data;
+        }
+    }
+
+    /// $3F10/$3F14/$3F18/$3F1C are mirrors of the backdrop entries below them
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1f) as usize;
+        if index & 0x13 == 0x10 {
+            index & 0x0f
+        } else {
+            index
+        }
+    }
+
+    fn read_vram(&mut self, addr: u16, mapper: &mut dyn Mapper) -> u8 {
+        let addr = addr & 0x3fff;
+        match addr {
+            0..=PATTERN_TABLES_END => mapper.ppu_read(addr),
+            NAMETABLES..=NAMETABLES_MIRRORS_END => self.read_nametable(addr, mapper),
+            _ => self.palette_table[Self::palette_index(addr)],
+        }
+    }
+
+    fn write_vram(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
+        let addr = addr & 0x3fff;
+        match addr {
+            0..=PATTERN_TABLES_END => mapper.ppu_write(addr, data),
+            NAMETABLES..=NAMETABLES_MIRRORS_END => self.write_nametable(addr, data, mapper),
+            _ => self.palette_table[Self::palette_index(addr)] = data & 0x3f,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::mapper;
+    use crate::rom::test::test_rom;
+
+    pub fn set_vram_addr(ppu: &mut PPU, mapper: &mut dyn Mapper, addr: u16) {
+        ppu.write_register(0x2006, (addr >> 8) as u8, mapper);
+        ppu.write_register(0x2006, addr as u8, mapper);
+    }
+
+    /// Fills VRAM from `addr` on through PPUDATA
+    pub fn write_vram(ppu: &mut PPU, mapper: &mut dyn Mapper, addr: u16, data: &[u8]) {
+        set_vram_addr(ppu, mapper, addr);
+        for &byte in data {
+            ppu.write_register(0x2007, byte, mapper);
+        }
+    }
+
+    /// Points t back at nametable 0 scrolled by (x, y), as games do once
+    /// they are done writing VRAM
+    pub fn set_scroll(ppu: &mut PPU, mapper: &mut dyn Mapper, x: u8, y: u8) {
+        let ctrl = ppu.ctrl.bits() & !0b11;
+        ppu.write_register(0x2000, ctrl, mapper);
+        ppu.write_register(0x2005, x, mapper);
+        ppu.write_register(0x2005, y, mapper);
+    }
+
+    /// Ticks until the PPU is about to run `dot` of `scanline`
+    pub fn run_to(ppu: &mut PPU, mapper: &mut dyn Mapper, scanline: u16, dot: u16) {
+        loop {
+            ppu.tick(mapper);
+            if ppu.scanline == scanline && ppu.dot == dot {
+                break;
+            }
+        }
+    }
+
+    pub fn run_frames(ppu: &mut PPU, mapper: &mut dyn Mapper, frames: usize) {
+        for _ in 0..frames * DOTS_PER_SCANLINE as usize * SCANLINES_PER_FRAME as usize {
+            ppu.tick(mapper);
+        }
+    }
+
+    #[test]
+    fn test_vram_read_is_buffered() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x2305);
+        ppu.write_register(0x2007, 0x66, mapper.as_mut());
+        ppu.write_register(0x2007, 0x77, mapper.as_mut());
+
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x2305);
+        ppu.read_register(0x2007, mapper.as_mut());
+        assert_eq!(ppu.read_register(0x2007, mapper.as_mut()), 0x66);
+        assert_eq!(ppu.read_register(0x2007, mapper.as_mut()), 0x77);
+    }
+
+    #[test]
+    fn test_vram_increment_32() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2000, 0b100, mapper.as_mut());
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x21ff);
+        ppu.write_register(0x2007, 0x66, mapper.as_mut());
+        ppu.write_register(0x2007, 0x77, mapper.as_mut());
+
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x21ff);
+        ppu.read_register(0x2007, mapper.as_mut());
+        assert_eq!(ppu.read_register(0x2007, mapper.as_mut()), 0x66);
+        assert_eq!(ppu.vram[0x21f], 0x77);
+    }
+
+    #[test]
+    fn test_chr_through_mapper() {
+        let mut rom = test_rom();
+        rom.chr_rom[0x1234] = 0x42;
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(rom);
+
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x1234);
+        ppu.read_register(0x2007, mapper.as_mut());
+        assert_eq!(ppu.read_register(0x2007, mapper.as_mut()), 0x42);
+    }
+
+    #[test]
+    fn test_palette_reads_are_immediate() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x3f10);
+        ppu.write_register(0x2007, 0x2c, mapper.as_mut());
+
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x3f00);
+        assert_eq!(ppu.read_register(0x2007, mapper.as_mut()), 0x2c);
+        set_vram_addr(&mut ppu, mapper.as_mut(), 0x3f11);
+        ppu.write_register(0x2007, 0x15, mapper.as_mut());
+        assert_eq!(ppu.palette_table[0x11], 0x15);
+    }
+
+    #[test]
+    fn test_status_read_resets_latch() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.status.insert(StatusRegister::VBLANK_STARTED);
+        ppu.write_register(0x2006, 0x21, mapper.as_mut());
+
+        assert_eq!(ppu.read_register(0x2002, mapper.as_mut()) >> 7, 1);
+        assert_eq!(ppu.read_register(0x2002, mapper.as_mut()) >> 7, 0);
+
+        ppu.write_register(0x2006, 0x23, mapper.as_mut());
+        ppu.write_register(0x2006, 0x05, mapper.as_mut());
+        assert_eq!(ppu.v, 0x2305);
+    }
+
+    #[test]
+    fn test_loopy_registers() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2000, 0b11, mapper.as_mut());
+        assert_eq!(ppu.t, 0x0c00);
+
+        ppu.write_register(0x2005, 0b0111_1101, mapper.as_mut());
+        assert_eq!(ppu.t, 0x0c0f);
+        assert_eq!(ppu.fine_x, 0b101);
+        assert!(ppu.w);
+        ppu.write_register(0x2005, 0b0101_1110, mapper.as_mut());
+        assert_eq!(ppu.t, 0x6d6f);
+        assert!(!ppu.w);
+
+        ppu.write_register(0x2006, 0x3d, mapper.as_mut());
+        assert_eq!(ppu.t, 0x3d6f);
+        assert_eq!(ppu.v, 0);
+        ppu.write_register(0x2006, 0xf0, mapper.as_mut());
+        assert_eq!(ppu.t, 0x3df0);
+        assert_eq!(ppu.v, 0x3df0);
+
+        // a status read in between restarts the write pair
+        ppu.write_register(0x2005, 0xff, mapper.as_mut());
+        ppu.read_register(0x2002, mapper.as_mut());
+        ppu.write_register(0x2005, 0x00, mapper.as_mut());
+        assert_eq!(ppu.t & COARSE_X, 0);
+        assert_eq!(ppu.fine_x, 0);
+    }
+
+    #[test]
+    fn test_ppudata_while_rendering() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2001, 0b0000_1000, mapper.as_mut());
+        run_to(&mut ppu, mapper.as_mut(), 10, 300);
+
+        // coarse X and fine Y step instead of v + 1
+        let before = ppu.v;
+        ppu.write_register(0x2007, 0, mapper.as_mut());
+        assert_eq!(ppu.v, before + 0x1001);
+    }
+
+    #[test]
+    fn test_vblank() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2000, 0x80, mapper.as_mut());
+
+        run_to(&mut ppu, mapper.as_mut(), 241, 1);
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        ppu.tick(mapper.as_mut());
+        assert!(ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(ppu.poll_nmi());
+        assert!(!ppu.poll_nmi());
+        assert!(ppu.take_frame_complete());
+        assert!(!ppu.take_frame_complete());
+
+        run_to(&mut ppu, mapper.as_mut(), 261, 2);
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_nmi_enabled_during_vblank() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        run_to(&mut ppu, mapper.as_mut(), 250, 0);
+        assert!(!ppu.poll_nmi());
+
+        ppu.write_register(0x2000, 0x80, mapper.as_mut());
+        assert!(ppu.poll_nmi());
+        // rewriting the enabled bit is not another edge
+        ppu.write_register(0x2000, 0x80, mapper.as_mut());
+        assert!(!ppu.poll_nmi());
+    }
+
+    /// Dots from the current position until the next frame starts
+    fn frame_length(ppu: &mut PPU, mapper: &mut dyn Mapper) -> usize {
+        let mut dots = 0;
+        loop {
+            ppu.tick(mapper);
+            dots += 1;
+            if ppu.scanline == 0 && ppu.dot == 0 {
+                return dots;
+            }
+        }
+    }
+
+    #[test]
+    fn test_odd_frame_skips_dot() {
+        let full = DOTS_PER_SCANLINE as usize * SCANLINES_PER_FRAME as usize;
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full);
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full);
+
+        ppu.write_register(0x2001, 0b0000_1000, mapper.as_mut());
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full);
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full - 1);
+    }
+
+    #[test]
+    fn test_pal_frame() {
+        let full = DOTS_PER_SCANLINE as usize * PAL_SCANLINES_PER_FRAME as usize;
+        let mut ppu = PPU::with_timing(Timing::Pal);
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2001, 0b0000_1000, mapper.as_mut());
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full);
+        assert_eq!(frame_length(&mut ppu, mapper.as_mut()), full);
+    }
+
+    #[test]
+    fn test_oam() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x2003, 0x10, mapper.as_mut());
+        ppu.write_register(0x2004, 0x66, mapper.as_mut());
+        ppu.write_register(0x2004, 0x77, mapper.as_mut());
+
+        ppu.write_register(0x2003, 0x10, mapper.as_mut());
+        assert_eq!(ppu.read_register(0x2004, mapper.as_mut()), 0x66);
+        ppu.write_register(0x2003, 0x11, mapper.as_mut());
+        assert_eq!(ppu.read_register(0x2004, mapper.as_mut()), 0x77);
+    }
+
+    #[test]
+    fn test_register_mirrors() {
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(test_rom());
+        ppu.write_register(0x3ffe, 0x24, mapper.as_mut());
+        ppu.write_register(0x200e, 0x00, mapper.as_mut());
+        ppu.write_register(0x3fff, 0x99, mapper.as_mut());
+        assert_eq!(ppu.vram[0x000], 0x99);
+    }
+
+    fn mirrored_reads(mirroring: Mirroring) -> Vec<u8> {
+        let mut rom = test_rom();
+        rom.screen_mirroring = mirroring;
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(rom);
+
+        for (i, addr) in [0x2005, 0x2405, 0x2805, 0x2c05].into_iter().enumerate() {
+            set_vram_addr(&mut ppu, mapper.as_mut(), addr);
+            ppu.write_register(0x2007, i as u8 + 1, mapper.as_mut());
+        }
+
+        [0x2005, 0x2405, 0x2805, 0x2c05, 0x3005]
+            .into_iter()
+            .map(|addr| {
+                set_vram_addr(&mut ppu, mapper.as_mut(), addr);
+                ppu.read_register(0x2007, mapper.as_mut());
+                ppu.read_register(0x2007, mapper.as_mut())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_horizontal_mirroring() {
+        assert_eq!(mirrored_reads(Mirroring::Horizontal), vec![2, 2, 4, 4, 2]);
+    }
+
+    #[test]
+    fn test_vertical_mirroring() {
+        assert_eq!(mirrored_reads(Mirroring::Vertical), vec![3, 4, 3, 4, 3]);
+    }
+
+    #[test]
+    fn test_four_screen() {
+        assert_eq!(mirrored_reads(Mirroring::FourScreen), vec![1, 2, 3, 4, 1]);
+    }
+
+    #[test]
+    fn test_single_screen() {
+        assert_eq!(PPU::vram_index(0x2c05, Mirroring::SingleScreenLower), 0x005);
+        assert_eq!(PPU::vram_index(0x2005, Mirroring::SingleScreenUpper), 0x405);
+    }
+}
@@ -0,0 +1,60 @@
+bitflags! {
+    /// PPUCTRL ($2000)
+    pub struct ControlRegister: u8 {
+        const NAMETABLE1              = 0b0000_0001;
+        const NAMETABLE2              = 0b0000_0010;
+        const VRAM_ADD_INCREMENT      = 0b0000_0100;
+        const SPRITE_PATTERN_ADDR     = 0b0000_1000;
+        const BACKGROUND_PATTERN_ADDR = 0b0001_0000;
+        const SPRITE_SIZE             = 0b0010_0000;
+        const MASTER_SLAVE_SELECT     = 0b0100_0000;
+        const GENERATE_NMI            = 0b1000_0000;
+    }
+}
+
+impl ControlRegister {
+    /// PPUDATA accesses step across a row (1) or down a column (32)
+    pub fn vram_addr_increment(&self) -> u16 {
+        if self.contains(ControlRegister::VRAM_ADD_INCREMENT) {
+            32
+        } else {
+            1
+        }
+    }
+
+    pub fn background_pattern_addr(&self) -> u16 {
+        if self.contains(ControlRegister::BACKGROUND_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    /// Base nametable select, bits 10 and 11 of a VRAM address
+    pub fn nametable_bits(&self) -> u16 {
+        (self.bits() as u16 & 0b11) << 10
+    }
+}
+
+bitflags! {
+    /// PPUMASK ($2001)
+    pub struct MaskRegister: u8 {
+        const GREYSCALE            = 0b0000_0001;
+        const SHOW_BACKGROUND_LEFT = 0b0000_0010;
+        const SHOW_SPRITES_LEFT    = 0b0000_0100;
+        const SHOW_BACKGROUND      = 0b0000_1000;
+        const SHOW_SPRITES         = 0b0001_0000;
+        const EMPHASISE_RED        = 0b0010_0000;
+        const EMPHASISE_GREEN      = 0b0100_0000;
+        const EMPHASISE_BLUE       = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    /// PPUSTATUS ($2002), the low 5 bits read back as open bus
+    pub struct StatusRegister: u8 {
+        const SPRITE_OVERFLOW = 0b0010_0000;
+        const SPRITE_ZERO_HIT = 0b0100_0000;
+        const VBLANK_STARTED  = 0b1000_0000;
+    }
+}
@@ -0,0 +1,338 @@
+use super::registers::ControlRegister;
+use super::registers::MaskRegister;
+use super::registers::StatusRegister;
+use super::PPU;
+use super::VISIBLE_SCANLINES;
+use crate::mapper::Mapper;
+
+const MAX_SPRITES_PER_LINE: usize = 8;
+const OAM_SPRITES: usize = 64;
+
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0011;
+const ATTRIBUTE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const ATTRIBUTE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const ATTRIBUTE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// A sprite fetched for the current line, patterns already flipped
+#[derive(Default, Clone, Copy)]
+struct SpriteUnit {
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+/// Secondary OAM, filled by evaluation on one line and fetched into the
+/// sprite units drawn on the next
+#[derive(Default)]
+pub(super) struct Sprites {
+    secondary: [[u8; 4]; MAX_SPRITES_PER_LINE],
+    secondary_count: usize,
+    secondary_has_zero: bool,
+    units: [SpriteUnit; MAX_SPRITES_PER_LINE],
+    unit_count: usize,
+    units_have_zero: bool,
+}
+
+/// An opaque sprite pixel
+pub(super) struct SpritePixel {
+    /// Index into the sprite half of the palette
+    pub(super) color: u8,
+    pub(super) behind_background: bool,
+    pub(super) sprite_zero: bool,
+}
+
+impl PPU {
+    /// Evaluates and fetches the sprites of the next line on dots 257-320
+    pub(super) fn sprite_dot(&mut self, mapper: &mut dyn Mapper) {
+        let dot = self.dot;
+        match dot {
+            257 => {
+                mapper.sprite_fetch(true);
+                if self.scanline < VISIBLE_SCANLINES {
+                    self.evaluate_sprites();
+                } else {
+                    // no evaluation on the pre-render line, line 0 never has sprites
+                    self.sprites.secondary_count = 0;
+                    self.sprites.secondary_has_zero = false;
+                }
+                self.sprites.unit_count = self.sprites.secondary_count;
+                self.sprites.units_have_zero = self.sprites.secondary_has_zero;
+            }
+            321 => mapper.sprite_fetch(false),
+            _ => {}
+        }
+
+        if (257..=320).contains(&dot) {
+            self.oam_addr = 0;
+            let slot = (dot - 257) as usize / 8;
+            match (dot - 257) % 8 {
+                4 => self.fetch_sprite_pattern(mapper, slot, 0),
+                6 => self.fetch_sprite_pattern(mapper, slot, 8),
+                _ => {}
+            }
+        }
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl.contains(ControlRegister::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    /// Copies the first 8 sprites in range of the current line to secondary
+    /// OAM. The overflow search after that reproduces the hardware bug of
+    /// stepping through the other bytes of each entry as if they were Y
+    fn evaluate_sprites(&mut self) {
+        let height = self.sprite_height();
+        let scanline = self.scanline;
+        let in_range = |y: u8| scanline.wrapping_sub(y as u16) < height;
+
+        let sprites = &mut self.sprites;
+        sprites.secondary_count = 0;
+        sprites.secondary_has_zero = false;
+
+        let mut n = 0;
+        while n < OAM_SPRITES && sprites.secondary_count < MAX_SPRITES_PER_LINE {
+            let entry = &self.oam_data[n * 4..n * 4 + 4];
+            if in_range(entry[0]) {
+                sprites.secondary[sprites.secondary_count].copy_from_slice(entry);
+                sprites.secondary_count += 1;
+                sprites.secondary_has_zero |= n == 0;
+            }
+            n += 1;
+        }
+
+        let mut m = 0;
+        while n < OAM_SPRITES {
+            if in_range(self.oam_data[n * 4 + m]) {
+                self.status.insert(StatusRegister::SPRITE_OVERFLOW);
+                break;
+            }
+            n += 1;
+            m = (m + 1) & 0b11;
+        }
+    }
+
+    /// Empty slots still fetch tile $FF, which mappers counting A12 rises
+    /// rely on
+    fn fetch_sprite_pattern(&mut self, mapper: &mut dyn Mapper, slot: usize, plane: u16) {
+        let height = self.sprite_height();
+        let occupied = slot < self.sprites.secondary_count;
+        let [y, tile, attributes, x] = if occupied {
+            self.sprites.secondary[slot]
+        } else {
+            [0xff; 4]
+        };
+
+        let mut row = self.scanline.wrapping_sub(y as u16) & (height - 1);
+        if attributes & ATTRIBUTE_FLIP_VERTICAL != 0 {
+            row = height - 1 - row;
+        }
+
+        let addr = if height == 16 {
+            let table = (tile as u16 & 0x01) * 0x1000;
+            let tile = (tile & 0xfe) as u16 + row / 8;
+            table + tile * 16 + row % 8
+        } else {
+            let table = if self.ctrl.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+                0x1000
+            } else {
+                0
+            };
+            table + tile as u16 * 16 + row
+        };
+
+        let mut data = mapper.ppu_read(addr + plane);
+        if !occupied {
+            return;
+        }
+        if attributes & ATTRIBUTE_FLIP_HORIZONTAL != 0 {
+            data = data.reverse_bits();
+        }
+
+        let unit = &mut self.sprites.units[slot];
+        unit.x = x;
+        unit.attributes = attributes;
+        if plane == 0 {
+            unit.pattern_low = data;
+        } else {
+            unit.pattern_high = data;
+        }
+    }
+
+    /// The frontmost opaque sprite pixel at the current dot
+    pub(super) fn sprite_pixel(&self) -> Option<SpritePixel> {
+        let x = self.dot - 1;
+        if !self.mask.contains(MaskRegister::SHOW_SPRITES)
+            || (x < 8 && !self.mask.contains(MaskRegister::SHOW_SPRITES_LEFT))
+        {
+            return None;
+        }
+
+        let sprites = &self.sprites;
+        sprites.units[..sprites.unit_count]
+            .iter()
+            .enumerate()
+            .find_map(|(i, unit)| {
+                let offset = x.wrapping_sub(unit.x as u16);
+                if offset >= 8 {
+                    return None;
+                }
+                let bit = 7 - offset;
+                let pattern = (unit.pattern_low >> bit) & 1 | ((unit.pattern_high >> bit) & 1) << 1;
+                if pattern == 0 {
+                    return None;
+                }
+                Some(SpritePixel {
+                    color: (unit.attributes & ATTRIBUTE_PALETTE) << 2 | pattern,
+                    behind_background: unit.attributes & ATTRIBUTE_BEHIND_BACKGROUND != 0,
+                    sprite_zero: i == 0 && sprites.units_have_zero,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{run_frames, run_to, set_scroll, write_vram};
+    use super::*;
+    use crate::mapper;
+    use crate::rom::test::test_rom;
+
+    const BACKDROP: u8 = 0x0f;
+    const BACKGROUND: u8 = 0x30;
+    const SPRITE: u8 = 0x16;
+
+    /// Tile 1 is solid colour 1, tile 2 only has its leftmost column set.
+    /// The background is tile 1 across the top 8 lines, transparent below
+    fn setup() -> (PPU, Box<dyn Mapper>) {
+        let mut rom = test_rom();
+        for row in 0..8 {
+            rom.chr_rom[16 + row] = 0xff;
+            rom.chr_rom[32 + row] = 0x80;
+        }
+        let mut ppu = PPU::new();
+        let mut mapper = mapper::new(rom);
+
+        write_vram(&mut ppu, mapper.as_mut(), 0x2000, &[1; 32]);
+        write_vram(&mut ppu, mapper.as_mut(), 0x3f00, &[BACKDROP, BACKGROUND]);
+        write_vram(&mut ppu, mapper.as_mut(), 0x3f11, &[SPRITE]);
+        ppu.write_register(0x2001, 0b0001_1110, mapper.as_mut());
+        set_scroll(&mut ppu, mapper.as_mut(), 0, 0);
+        (ppu, mapper)
+    }
+
+    fn set_sprite(ppu: &mut PPU, index: usize, entry: [u8; 4]) {
+        ppu.oam_data[index * 4..index * 4 + 4].copy_from_slice(&entry);
+    }
+
+    fn hide_sprites(ppu: &mut PPU) {
+        ppu.oam_data = [0xff; 256];
+    }
+
+    #[test]
+    fn test_sprite() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [19, 1, 0, 20]);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(20, 20), SPRITE);
+        assert_eq!(ppu.frame.pixel(27, 27), SPRITE);
+        assert_eq!(ppu.frame.pixel(19, 20), BACKDROP);
+        assert_eq!(ppu.frame.pixel(28, 20), BACKDROP);
+        assert_eq!(ppu.frame.pixel(20, 19), BACKDROP);
+        assert_eq!(ppu.frame.pixel(20, 28), BACKDROP);
+    }
+
+    #[test]
+    fn test_flips() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [19, 2, 0, 20]);
+        set_sprite(&mut ppu, 1, [19, 2, ATTRIBUTE_FLIP_HORIZONTAL, 40]);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(20, 20), SPRITE);
+        assert_eq!(ppu.frame.pixel(21, 20), BACKDROP);
+        assert_eq!(ppu.frame.pixel(46, 20), BACKDROP);
+        assert_eq!(ppu.frame.pixel(47, 20), SPRITE);
+    }
+
+    #[test]
+    fn test_8x16() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        ppu.write_register(0x2000, 0b0010_0000, mapper.as_mut());
+        // tiles 2 and 3, flipped so tile 2 ends up at the bottom
+        set_sprite(&mut ppu, 0, [19, 2, ATTRIBUTE_FLIP_VERTICAL, 20]);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(20, 27), BACKDROP);
+        assert_eq!(ppu.frame.pixel(20, 28), SPRITE);
+        assert_eq!(ppu.frame.pixel(20, 35), SPRITE);
+        assert_eq!(ppu.frame.pixel(20, 36), BACKDROP);
+    }
+
+    #[test]
+    fn test_priority() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [0, 1, ATTRIBUTE_BEHIND_BACKGROUND, 20]);
+        set_sprite(&mut ppu, 1, [0, 1, 0, 40]);
+        run_frames(&mut ppu, mapper.as_mut(), 2);
+
+        assert_eq!(ppu.frame.pixel(20, 1), BACKGROUND);
+        assert_eq!(ppu.frame.pixel(20, 8), SPRITE);
+        assert_eq!(ppu.frame.pixel(40, 1), SPRITE);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        set_sprite(&mut ppu, 0, [20, 1, 0, 20]);
+        // the flag is checked before the pre-render line clears it
+        run_to(&mut ppu, mapper.as_mut(), VISIBLE_SCANLINES, 0);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+        set_sprite(&mut ppu, 0, [4, 1, 0, 20]);
+        run_to(&mut ppu, mapper.as_mut(), VISIBLE_SCANLINES, 0);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+    }
+
+    #[test]
+    fn test_sprite_limit_and_overflow() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        for i in 0..8 {
+            set_sprite(&mut ppu, i, [19, 1, 0, i as u8 * 8]);
+        }
+        run_to(&mut ppu, mapper.as_mut(), VISIBLE_SCANLINES, 0);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+
+        set_sprite(&mut ppu, 8, [19, 1, 0, 100]);
+        run_to(&mut ppu, mapper.as_mut(), VISIBLE_SCANLINES, 0);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+        assert_eq!(ppu.frame.pixel(63, 20), SPRITE);
+        assert_eq!(ppu.frame.pixel(100, 20), BACKDROP);
+    }
+
+    #[test]
+    fn test_overflow_bug() {
+        let (mut ppu, mut mapper) = setup();
+        hide_sprites(&mut ppu);
+        for i in 0..8 {
+            set_sprite(&mut ppu, i, [19, 1, 0, i as u8 * 8]);
+        }
+        // the ninth sprite is off the line, but the search reads the next
+        // entry's tile index as a Y coordinate
+        set_sprite(&mut ppu, 8, [100, 1, 0, 0]);
+        set_sprite(&mut ppu, 9, [100, 20, 0, 0]);
+        run_to(&mut ppu, mapper.as_mut(), VISIBLE_SCANLINES, 0);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+    }
+}
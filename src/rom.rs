@@ -1,28 +1,191 @@
+use crate::mapper;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mirroring {
     Horizontal,
     Vertical,
     FourScreen,
+    /// All four nametables show the first 1K of VRAM, mapper controlled
+    SingleScreenLower,
+    /// All four nametables show the second 1K of VRAM, mapper controlled
+    SingleScreenUpper,
+}
+
+/// CPU/PPU timing the cartridge was made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    /// NES 2.0 extended console type from byte 13
+    Extended(u8),
 }
 
 const NES_MAGIC: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RomError {
+    /// The file is shorter than the 16 byte iNES header
+    TooShort(usize),
+    BadMagic,
+    /// The header is neither iNES nor NES 2.0 (e.g. archaic iNES with junk in byte 7)
+    UnsupportedFormat,
+    /// The header announces no PRG ROM at all
+    EmptyPrg,
+    /// The trainer flag is set but the file ends inside the trainer
+    TruncatedTrainer,
+    TruncatedPrg { expected: usize, found: usize },
+    TruncatedChr { expected: usize, found: usize },
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooShort(len) => {
+                write!(f, "file is {} bytes, too short for an iNES header", len)
+            }
+            RomError::BadMagic => write!(f, "invalid NES magic number"),
+            RomError::UnsupportedFormat => write!(f, "only iNES and NES 2.0 headers are supported"),
+            RomError::EmptyPrg => write!(f, "header declares no PRG ROM"),
+            RomError::TruncatedTrainer => write!(f, "file ends inside the 512 byte trainer"),
+            RomError::TruncatedPrg { expected, found } => {
+                write!(f, "PRG ROM truncated: expected {} bytes, found {}", expected, found)
+            }
+            RomError::TruncatedChr { expected, found } => {
+                write!(f, "CHR ROM truncated: expected {} bytes, found {}", expected, found)
+            }
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+        }
+    }
+}
+
+impl Error for RomError {}
 
 pub struct Rom {
    pub prg_rom: Vec<u8>,
    pub chr_rom: Vec<u8>,
-   pub mapper: u8,
+   /// 512 byte trainer, loaded into PRG RAM at $7000 on power-up
+   pub trainer: Option<Vec<u8>>,
+   pub mapper: u16,
+   pub submapper: u8,
    pub screen_mirroring: Mirroring,
+   /// NES 2.0 header, where the RAM sizes are declared rather than assumed
+   pub nes2: bool,
+   /// PRG RAM at $6000-$7FFF is battery backed and should outlive the session
+   pub battery: bool,
+   pub prg_ram_size: usize,
+   pub prg_nvram_size: usize,
+   pub chr_ram_size: usize,
+   pub chr_nvram_size: usize,
+   pub timing: Timing,
+   pub console_type: ConsoleType,
+   pub expansion_device: u8,
+}
+
+/// NES 2.0 ROM sizes: a 12 bit page count, or `2^E * (MM * 2 + 1)` bytes
+/// when the most significant nibble is $F
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0x0f {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize.checked_shl(exponent)
+            .and_then(|base| base.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * page_size
+    }
+}
+
+/// NES 2.0 RAM sizes are stored as a shift count of 64 bytes
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
 }
 
 impl Rom {
-    pub fn new(raw: &Vec<u8>) -> Result<Self, String> {
+    pub fn new(raw: &[u8]) -> Result<Self, RomError> {
+        if raw.len() < HEADER_SIZE {
+            return Err(RomError::TooShort(raw.len()))
+        }
+
         if raw[0..4] != NES_MAGIC {
-            return Err("Invalid NES magic number".to_owned())
+            return Err(RomError::BadMagic)
         }
 
-        let mapper = (raw[6] & 0xf0) | (raw[7] >> 4);
-        let ines_version = raw[7] & 0x0f;
-        if ines_version != 0 {
-            return Err("Only iNES version 0 is supported".to_owned())
+        let nes2 = match raw[7] & 0x0c {
+            0x00 => false,
+            0x08 => true,
+            _ => return Err(RomError::UnsupportedFormat),
+        };
+
+        let mut mapper = ((raw[7] & 0xf0) | (raw[6] >> 4)) as u16;
+        let submapper;
+        let prg_rom_size;
+        let chr_rom_size;
+        let prg_ram_size;
+        let prg_nvram_size;
+        let chr_ram_size;
+        let chr_nvram_size;
+        let timing;
+        let expansion_device;
+
+        if nes2 {
+            mapper |= ((raw[8] & 0x0f) as u16) << 8;
+            submapper = raw[8] >> 4;
+            prg_rom_size = nes2_rom_size(raw[4], raw[9] & 0x0f, PRG_ROM_PAGE_SIZE);
+            chr_rom_size = nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE);
+            prg_ram_size = nes2_ram_size(raw[10] & 0x0f);
+            prg_nvram_size = nes2_ram_size(raw[10] >> 4);
+            chr_ram_size = nes2_ram_size(raw[11] & 0x0f);
+            chr_nvram_size = nes2_ram_size(raw[11] >> 4);
+            timing = match raw[12] & 0b11 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            };
+            expansion_device = raw[15] & 0b0011_1111;
+        } else {
+            submapper = 0;
+            prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
+            chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+            // iNES counts PRG RAM in 8K units, with 0 meaning 8K for compatibility
+            prg_ram_size = raw[8].max(1) as usize * 0x2000;
+            prg_nvram_size = 0;
+            chr_ram_size = if chr_rom_size == 0 { CHR_ROM_PAGE_SIZE } else { 0 };
+            chr_nvram_size = 0;
+            timing = if raw[9] & 0x01 != 0 { Timing::Pal } else { Timing::Ntsc };
+            expansion_device = 0;
+        }
+
+        let console_type = match raw[7] & 0b11 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice10,
+            _ if nes2 => ConsoleType::Extended(raw[13] & 0x0f),
+            _ => return Err(RomError::UnsupportedFormat),
+        };
+
+        if !mapper::is_supported(mapper) {
+            return Err(RomError::UnsupportedMapper(mapper))
         }
 
         let four_screen = raw[6] & 0x08 != 0;
@@ -33,21 +196,306 @@ impl Rom {
             (false, false) => Mirroring::Horizontal,
         };
 
-        let prg_rom_size = raw[4] as usize * 0x4000;
-        let chr_rom_size = raw[5] as usize * 0x2000;
+        if prg_rom_size == 0 {
+            return Err(RomError::EmptyPrg)
+        }
+
+        let battery = raw[6] & 0x02 != 0;
+        let has_trainer = raw[6] & 0x04 != 0;
+        let trainer = if has_trainer {
+            let trainer = raw.get(HEADER_SIZE..HEADER_SIZE + TRAINER_SIZE)
+                .ok_or(RomError::TruncatedTrainer)?;
+            Some(trainer.to_vec())
+        } else {
+            None
+        };
+
+        let prg_rom_start = HEADER_SIZE + if has_trainer { TRAINER_SIZE } else { 0 };
 
-        let sikp_trainer = raw[6] & 0x04 != 0;
-        let prg_rom_start = 16 + if sikp_trainer { 512 } else { 0 };
-        let prg_rom_end = prg_rom_start + prg_rom_size;
+        let prg_rom_end = prg_rom_start.saturating_add(prg_rom_size);
+        let prg_rom = raw.get(prg_rom_start..prg_rom_end).ok_or(RomError::TruncatedPrg {
+            expected: prg_rom_size,
+            found: raw.len() - prg_rom_start,
+        })?;
 
         let chr_rom_start = prg_rom_end;
-        let chr_rom_end = chr_rom_start + chr_rom_size;
+        let chr_rom_end = chr_rom_start.saturating_add(chr_rom_size);
+        let chr_rom = raw.get(chr_rom_start..chr_rom_end).ok_or(RomError::TruncatedChr {
+            expected: chr_rom_size,
+            found: raw.len() - chr_rom_start,
+        })?;
 
         Ok(Self {
-            prg_rom: raw[prg_rom_start..prg_rom_end].to_vec(),
-            chr_rom: raw[chr_rom_start..chr_rom_end].to_vec(),
+            prg_rom: prg_rom.to_vec(),
+            chr_rom: chr_rom.to_vec(),
+            trainer,
             mapper,
+            submapper,
             screen_mirroring,
+            nes2,
+            battery,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            timing,
+            console_type,
+            expansion_device,
         })
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    pub struct TestRom {
+        pub header: Vec<u8>,
+        pub trainer: Option<Vec<u8>>,
+        pub prg_rom: Vec<u8>,
+        pub chr_rom: Vec<u8>,
+    }
+
+    pub fn create_rom(rom: TestRom) -> Vec<u8> {
+        let mut result = Vec::with_capacity(
+            rom.header.len()
+                + rom.trainer.as_ref().map_or(0, |t| t.len())
+                + rom.prg_rom.len()
+                + rom.chr_rom.len(),
+        );
+
+        result.extend(&rom.header);
+        if let Some(t) = rom.trainer {
+            result.extend(t);
+        }
+        result.extend(&rom.prg_rom);
+        result.extend(&rom.chr_rom);
+
+        result
+    }
+
+    /// A blank NROM cartridge whose NMI, reset and IRQ vectors all point at
+    /// $0600, where `CPU::load` places raw test programs
+    pub fn test_rom() -> Rom {
+        let mut prg_rom = vec![0; PRG_ROM_PAGE_SIZE];
+        for vector in [0x3ffa, 0x3ffc, 0x3ffe] {
+            prg_rom[vector] = 0x00;
+            prg_rom[vector + 1] = 0x06;
+        }
+
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom,
+            chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+        });
+
+        Rom::new(&test_rom).unwrap()
+    }
+
+    #[test]
+    fn test() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom: Rom = Rom::new(&test_rom).unwrap();
+
+        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        assert_eq!(rom.trainer, None);
+        assert_eq!(rom.mapper, 0);
+        assert!(matches!(rom.screen_mirroring, Mirroring::Vertical));
+    }
+
+    #[test]
+    fn test_too_short() {
+        assert_eq!(Rom::new(&[]).err(), Some(RomError::TooShort(0)));
+        assert_eq!(Rom::new(&NES_MAGIC).err(), Some(RomError::TooShort(4)));
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let raw = vec![0x4E, 0x45, 0x53, 0x00, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00];
+        assert_eq!(Rom::new(&raw).err(), Some(RomError::BadMagic));
+    }
+
+    #[test]
+    fn test_ines_defaults() {
+        let rom = test_rom();
+        assert!(!rom.nes2);
+        assert_eq!(rom.submapper, 0);
+        assert!(!rom.battery);
+        assert_eq!(rom.prg_ram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0);
+        assert_eq!(rom.timing, Timing::Ntsc);
+        assert_eq!(rom.console_type, ConsoleType::Nes);
+    }
+
+    #[test]
+    fn test_nes2() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x00, 0x01, 0x08, 0x30, 0x00, 0x70, 0x07, 0x01, 0x00,
+                0x00, 0x01,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&test_rom).unwrap();
+        assert!(rom.nes2);
+        assert_eq!(rom.mapper, 0);
+        assert_eq!(rom.submapper, 3);
+        assert_eq!(rom.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.prg_ram_size, 0);
+        assert_eq!(rom.prg_nvram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.chr_nvram_size, 0);
+        assert_eq!(rom.timing, Timing::Pal);
+        assert_eq!(rom.console_type, ConsoleType::Nes);
+        assert_eq!(rom.expansion_device, 1);
+    }
+
+    #[test]
+    fn test_battery() {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![0; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![0; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+        assert!(rom.battery);
+        assert_eq!(rom.prg_ram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_nes2_mapper_msb() {
+        let raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00];
+        assert_eq!(Rom::new(&raw).err(), Some(RomError::UnsupportedMapper(0x100)));
+    }
+
+    #[test]
+    fn test_nes2_exponent_size() {
+        // 2^9 * (1 * 2 + 1) = 1536 bytes of PRG ROM
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0b0010_0101, 0x00, 0x00, 0x08, 0x00, 0x0f, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; 1536],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&test_rom).unwrap();
+        assert_eq!(rom.prg_rom.len(), 1536);
+    }
+
+    #[test]
+    fn test_archaic_header_is_not_supported() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        assert_eq!(Rom::new(&test_rom).err(), Some(RomError::UnsupportedFormat));
+    }
+
+    #[test]
+    fn test_truncated_prg() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+        assert_eq!(
+            Rom::new(&test_rom).err(),
+            Some(RomError::TruncatedPrg { expected: 2 * PRG_ROM_PAGE_SIZE, found: PRG_ROM_PAGE_SIZE })
+        );
+    }
+
+    #[test]
+    fn test_truncated_chr() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; 100],
+        });
+        assert_eq!(
+            Rom::new(&test_rom).err(),
+            Some(RomError::TruncatedChr { expected: CHR_ROM_PAGE_SIZE, found: 100 })
+        );
+    }
+
+    #[test]
+    fn test_with_trainer() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x01 | 0b100, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ],
+            trainer: Some(vec![3; TRAINER_SIZE]),
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom: Rom = Rom::new(&test_rom).unwrap();
+
+        assert_eq!(rom.trainer, Some(vec![3; TRAINER_SIZE]));
+        assert_eq!(rom.chr_rom, vec!(2; CHR_ROM_PAGE_SIZE));
+        assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
+        assert!(matches!(rom.screen_mirroring, Mirroring::Vertical));
+    }
+
+    #[test]
+    fn test_truncated_trainer() {
+        let raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0xff];
+        assert_eq!(Rom::new(&raw).err(), Some(RomError::TruncatedTrainer));
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+        assert_eq!(Rom::new(&test_rom).err(), Some(RomError::UnsupportedMapper(15)));
+    }
+}
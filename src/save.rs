@@ -0,0 +1,120 @@
+use crate::bus::Bus;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Battery backed RAM persisted to a `.sav` file next to the ROM
+pub struct SaveFile {
+    path: PathBuf,
+    /// What is on disk, so unchanged RAM is not rewritten
+    saved: Vec<u8>,
+}
+
+impl SaveFile {
+    pub fn for_rom(rom_path: &Path) -> Self {
+        SaveFile {
+            path: rom_path.with_extension("sav"),
+            saved: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copies an existing save into the cartridge. A missing file is not an
+    /// error, the game simply starts without a save
+    pub fn load(&mut self, bus: &mut Bus) -> io::Result<()> {
+        if bus.battery_ram().is_none() {
+            return Ok(());
+        }
+
+        match fs::read(&self.path) {
+            Ok(data) => {
+                bus.load_battery_ram(&data);
+                self.saved = data;
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the cartridge RAM out if it changed since the last flush
+    pub fn flush(&mut self, bus: &Bus) -> io::Result<()> {
+        let ram = match bus.battery_ram() {
+            Some(ram) => ram,
+            None => return Ok(()),
+        };
+        if ram == self.saved.as_slice() {
+            return Ok(());
+        }
+
+        fs::write(&self.path, ram)?;
+        self.saved = ram.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::Mem;
+    use crate::rom::test::{create_rom, TestRom};
+    use crate::rom::Rom;
+
+    fn battery_bus() -> Bus {
+        let raw = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00,
+            ],
+            trainer: None,
+            prg_rom: vec![0; 0x4000],
+            chr_rom: vec![0; 0x2000],
+        });
+        Bus::new(Rom::new(&raw).unwrap())
+    }
+
+    fn rom_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nes-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let rom_path = rom_path("round_trip.nes");
+        let mut save = SaveFile::for_rom(&rom_path);
+        assert_eq!(save.path(), rom_path.with_extension("sav"));
+        let _ = fs::remove_file(save.path());
+
+        let mut bus = battery_bus();
+        save.load(&mut bus).unwrap();
+        assert_eq!(bus.mem_read(0x6000), 0);
+
+        bus.mem_write(0x6000, 0x12);
+        bus.mem_write(0x7fff, 0x34);
+        save.flush(&bus).unwrap();
+        assert_eq!(fs::read(save.path()).unwrap().len(), 0x2000);
+
+        let mut bus = battery_bus();
+        SaveFile::for_rom(&rom_path).load(&mut bus).unwrap();
+        assert_eq!(bus.mem_read(0x6000), 0x12);
+        assert_eq!(bus.mem_read(0x7fff), 0x34);
+
+        fs::remove_file(save.path()).unwrap();
+    }
+
+    #[test]
+    fn test_no_battery() {
+        let rom_path = rom_path("no_battery.nes");
+        let mut save = SaveFile::for_rom(&rom_path);
+
+        let mut bus = Bus::new(crate::rom::test::test_rom());
+        bus.mem_write(0x6000, 0x12);
+        save.flush(&bus).unwrap();
+        assert!(!save.path().exists());
+    }
+}
@@ -0,0 +1,55 @@
+use nes::bus::Bus;
+use nes::cpu::CPU;
+use nes::rom::Rom;
+use nes::trace::trace;
+use std::fs;
+use std::path::Path;
+
+/// Runs nestest.nes in automation mode (starting at $C000) and compares
+/// every traced instruction against the reference nestest.log. Neither file
+/// is vendored yet: copy both into tests/fixtures and run
+/// `cargo test -- --ignored`
+#[test]
+#[ignore = "needs tests/fixtures/nestest.nes and tests/fixtures/nestest.log"]
+fn nestest() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let rom_path = fixtures.join("nestest.nes");
+    let log_path = fixtures.join("nestest.log");
+
+    let raw = fs::read(&rom_path)
+        .unwrap_or_else(|err| panic!("missing fixture {}: {}", rom_path.display(), err));
+    let log = fs::read_to_string(&log_path)
+        .unwrap_or_else(|err| panic!("missing fixture {}: {}", log_path.display(), err));
+    let expected: Vec<&str> = log.lines().map(|line| line.trim_end()).collect();
+
+    let mut cpu = CPU::new(Bus::new(Rom::new(&raw).unwrap()));
+    cpu.reset();
+    cpu.program_count = 0xC000;
+
+    let mut line = 0;
+    let mut divergence = None;
+    cpu.run_with_callback(|cpu| {
+        if line == expected.len() {
+            cpu.stop();
+            return;
+        }
+
+        let actual = trace(cpu);
+        if actual != expected[line] {
+            divergence = Some(actual);
+            cpu.stop();
+            return;
+        }
+        line += 1;
+    });
+
+    if let Some(actual) = divergence {
+        panic!(
+            "nestest diverged at line {}\nexpected: {}\n  actual: {}",
+            line + 1,
+            expected[line],
+            actual
+        );
+    }
+    assert_eq!(line, expected.len());
+}